@@ -0,0 +1,13 @@
+use anyhow::Result;
+use bot::BotRunner;
+use bot::bots::DeployNotifier;
+
+// cargo run -p bot --example deploy_notifier -- [address] [log]
+// Then announce a deploy with e.g. `echo "api v1.4.2 to production" >> deploys.log`
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let log = args.next().unwrap_or_else(|| "deploys.log".to_string());
+    BotRunner::new(address, DeployNotifier::new(log)).room("deploys").run().await
+}
@@ -0,0 +1,10 @@
+use anyhow::Result;
+use bot::BotRunner;
+use bot::bots::DiceRoller;
+
+// cargo run -p bot --example dice_roller -- [address]
+#[tokio::main]
+async fn main() -> Result<()> {
+    let address = std::env::args().nth(1).unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    BotRunner::new(address, DiceRoller::new()).run().await
+}
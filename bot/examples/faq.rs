@@ -0,0 +1,15 @@
+use anyhow::Result;
+use bot::BotRunner;
+use bot::bots::FaqBot;
+
+// cargo run -p bot --example faq -- [address]
+#[tokio::main]
+async fn main() -> Result<()> {
+    let address = std::env::args().nth(1).unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let bot = FaqBot::new()
+        .answer("rooms", "Type /join <room> to move to another room, /who to see who is in yours")
+        .answer("nick", "Change your nickname with /nick <name>")
+        .answer("help", "/help lists every slash command")
+        .greet(true);
+    BotRunner::new(address, bot).run().await
+}
@@ -0,0 +1,105 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::{Bot, BotCommand, BotContext, Timer};
+
+const DEFAULT_POLL: Duration = Duration::from_secs(10);
+
+// Announces every line appended to a deploy log, e.g. written by the deploy script
+pub struct DeployNotifier {
+    path: PathBuf,
+    poll_every: Duration,
+    state: Mutex<DeployState>,
+}
+
+#[derive(Default)]
+struct DeployState {
+    // Lines of the log already handled; None until the log was first read
+    seen: Option<usize>,
+    last: Option<String>,
+}
+
+impl DeployState {
+    // The first successful read only remembers what is there
+    fn start_from(&mut self, lines: &[String]) {
+        self.seen = Some(lines.len());
+        self.last = lines.last().cloned();
+    }
+}
+
+impl DeployNotifier {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            poll_every: DEFAULT_POLL,
+            state: Mutex::new(DeployState::default()),
+        }
+    }
+
+    pub fn poll_every(mut self, every: Duration) -> Self {
+        self.poll_every = every;
+        self
+    }
+
+    // A missing log counts as empty, so the bot can start before the first deploy
+    async fn read_log(&self) -> Result<Vec<String>> {
+        match tokio::fs::read_to_string(&self.path).await {
+            Ok(log) => Ok(log.lines().filter(|line| !line.trim().is_empty()).map(str::to_string).collect()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl Bot for DeployNotifier {
+    fn name(&self) -> &str {
+        "deploys"
+    }
+
+    fn timers(&self) -> Vec<Timer> {
+        vec![Timer::new("poll", self.poll_every)]
+    }
+
+    // Deploys from before the bot started are not announced again
+    async fn on_connect(&self, _ctx: &BotContext) -> Result<()> {
+        let mut state = self.state.lock().await;
+        if state.seen.is_none() {
+            state.start_from(&self.read_log().await?);
+        }
+        Ok(())
+    }
+
+    async fn on_timer(&self, ctx: &BotContext, _timer: &str) -> Result<()> {
+        let lines = self.read_log().await?;
+        let mut state = self.state.lock().await;
+        let Some(seen) = state.seen else {
+            // Reading the log in on_connect failed
+            state.start_from(&lines);
+            return Ok(());
+        };
+        // A truncated or rotated log starts over
+        let seen = if seen <= lines.len() { seen } else { 0 };
+        state.seen = Some(seen);
+        for (index, line) in lines.iter().enumerate().skip(seen) {
+            ctx.say(&format!("Deployed: {}", line)).await?;
+            state.seen = Some(index + 1);
+            state.last = Some(line.clone());
+        }
+        Ok(())
+    }
+
+    async fn on_command(&self, ctx: &BotContext, command: &BotCommand) -> Result<()> {
+        if command.name != "lastdeploy" {
+            return Ok(());
+        }
+        let text = match &self.state.lock().await.last {
+            Some(last) => format!("Last deploy: {}", last),
+            None => "No deploys yet".to_string(),
+        };
+        ctx.reply(&command.message, &text).await
+    }
+}
@@ -0,0 +1,74 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::Mutex;
+
+use crate::{Bot, BotCommand, BotContext};
+
+const MAX_DICE: u32 = 100;
+const MAX_SIDES: u32 = 1000;
+const USAGE: &str = "Usage: !roll [N]dM, e.g. !roll 2d6";
+
+// Answers "!roll 2d6" with the single rolls and their sum
+pub struct DiceRoller {
+    rng: Mutex<StdRng>,
+}
+
+impl Default for DiceRoller {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DiceRoller {
+    pub fn new() -> Self {
+        Self { rng: Mutex::new(StdRng::from_os_rng()) }
+    }
+
+    // Same rolls every run, for tests
+    pub fn with_seed(seed: u64) -> Self {
+        Self { rng: Mutex::new(StdRng::seed_from_u64(seed)) }
+    }
+
+    fn roll(&self, dice: u32, sides: u32) -> Vec<u32> {
+        let mut rng = self.rng.lock().unwrap();
+        (0..dice).map(|_| rng.random_range(1..=sides)).collect()
+    }
+}
+
+// "2d6" -> (2, 6), "d20" -> (1, 20)
+fn parse_dice(spec: &str) -> Option<(u32, u32)> {
+    let spec = spec.to_ascii_lowercase();
+    let (dice, sides) = spec.split_once('d')?;
+    let dice = if dice.is_empty() { 1 } else { dice.parse().ok()? };
+    let sides = sides.parse().ok()?;
+    ((1..=MAX_DICE).contains(&dice) && (2..=MAX_SIDES).contains(&sides)).then_some((dice, sides))
+}
+
+#[async_trait]
+impl Bot for DiceRoller {
+    fn name(&self) -> &str {
+        "dice"
+    }
+
+    async fn on_command(&self, ctx: &BotContext, command: &BotCommand) -> Result<()> {
+        if command.name != "roll" {
+            return Ok(());
+        }
+        let spec = if command.args.is_empty() { "1d6" } else { command.args.as_str() };
+        let Some((dice, sides)) = parse_dice(spec) else {
+            return ctx.reply(&command.message, USAGE).await;
+        };
+
+        let rolls = self.roll(dice, sides);
+        let total: u32 = rolls.iter().sum();
+        let text = if rolls.len() == 1 {
+            format!("{} rolled {}d{}: {}", command.message.nickname, dice, sides, total)
+        } else {
+            let each: Vec<String> = rolls.iter().map(u32::to_string).collect();
+            format!("{} rolled {}d{}: {} = {}", command.message.nickname, dice, sides, each.join(" + "), total)
+        };
+        ctx.reply(&command.message, &text).await
+    }
+}
@@ -0,0 +1,79 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+
+use crate::{Bot, BotCommand, BotContext, ChatMessage, Member};
+
+// Answers "!faq topic", and questions that mention a topic, from a fixed list
+#[derive(Default)]
+pub struct FaqBot {
+    answers: BTreeMap<String, String>,
+    greet: bool,
+}
+
+impl FaqBot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn answer(mut self, topic: &str, answer: &str) -> Self {
+        self.answers.insert(topic.to_ascii_lowercase(), answer.to_string());
+        self
+    }
+
+    // Welcome everyone who comes online and point them at !faq
+    pub fn greet(mut self, greet: bool) -> Self {
+        self.greet = greet;
+        self
+    }
+
+    fn topics(&self) -> String {
+        let topics: Vec<&str> = self.answers.keys().map(String::as_str).collect();
+        format!("Ask me about: {}. Use !faq <topic>", topics.join(", "))
+    }
+
+    // The first topic named as a word in `question`
+    fn topic_in(&self, question: &str) -> Option<&str> {
+        let words: Vec<String> = question
+            .split(|c: char| !c.is_alphanumeric() && c != '-' && c != '_')
+            .map(str::to_ascii_lowercase)
+            .collect();
+        self.answers.keys().find(|topic| words.contains(topic)).map(String::as_str)
+    }
+}
+
+#[async_trait]
+impl Bot for FaqBot {
+    fn name(&self) -> &str {
+        "faq"
+    }
+
+    async fn on_command(&self, ctx: &BotContext, command: &BotCommand) -> Result<()> {
+        if command.name != "faq" {
+            return Ok(());
+        }
+        let topic = command.args.to_ascii_lowercase();
+        match self.answers.get(&topic) {
+            Some(answer) => ctx.reply(&command.message, answer).await,
+            None if topic.is_empty() => ctx.reply(&command.message, &self.topics()).await,
+            None => ctx.reply(&command.message, &format!("No answer about {}. {}", topic, self.topics())).await,
+        }
+    }
+
+    async fn on_message(&self, ctx: &BotContext, message: &ChatMessage) -> Result<()> {
+        if !message.content.trim_end().ends_with('?') {
+            return Ok(());
+        }
+        match self.topic_in(&message.content) {
+            Some(topic) => ctx.reply(message, &self.answers[topic]).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn on_online(&self, ctx: &BotContext, member: &Member) -> Result<()> {
+        if !self.greet {
+            return Ok(());
+        }
+        ctx.say(&format!("Welcome, {}! Type !faq for answers to common questions", member.nickname)).await
+    }
+}
@@ -0,0 +1,7 @@
+mod deploy;
+mod dice;
+mod faq;
+
+pub use deploy::DeployNotifier;
+pub use dice::DiceRoller;
+pub use faq::FaqBot;
@@ -0,0 +1,64 @@
+use anyhow::Result;
+use client_lib::{ClientHandle, display_name};
+use server::{ClientId, RoomId};
+use std::collections::HashMap;
+
+use crate::ChatMessage;
+
+// What a handler can see and do; `client` gives access to everything else client-lib offers
+pub struct BotContext {
+    client: ClientHandle,
+    pub(crate) room: RoomId,
+    pub(crate) nicknames: HashMap<ClientId, String>,
+}
+
+impl BotContext {
+    pub(crate) fn new(client: ClientHandle, room: RoomId) -> Self {
+        Self {
+            client,
+            room,
+            nicknames: HashMap::new(),
+        }
+    }
+
+    pub fn client(&self) -> &ClientHandle {
+        &self.client
+    }
+
+    pub fn own_id(&self) -> ClientId {
+        self.client.client_id()
+    }
+
+    // The room the bot is in
+    pub fn room(&self) -> &str {
+        &self.room
+    }
+
+    // Nickname of a known client, falling back to the short id
+    pub fn nickname(&self, client_id: &ClientId) -> String {
+        display_name(self.nicknames.get(client_id).map(String::as_str), client_id)
+    }
+
+    // Posts `text` to the bot's room as is, even when it starts with '/',
+    // and returns once the server accepted it
+    pub async fn say(&self, text: &str) -> Result<()> {
+        let text = if text.starts_with('/') { format!("/{}", text) } else { text.to_string() };
+        self.client.send_message(&text).await?.accepted().await?;
+        Ok(())
+    }
+
+    pub async fn reply(&self, to: &ChatMessage, text: &str) -> Result<()> {
+        let Some(parent) = to.id else {
+            return self.say(text).await;
+        };
+        let text = if text.starts_with('/') { format!("/{}", text) } else { text.to_string() };
+        self.client.reply(parent, &text).await?.accepted().await?;
+        Ok(())
+    }
+
+    // Runs a slash command as the bot, e.g. "/topic Deploy freeze"; answers arrive as System messages
+    pub async fn command(&self, line: &str) -> Result<()> {
+        self.client.send_command(line).await?;
+        Ok(())
+    }
+}
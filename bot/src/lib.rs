@@ -0,0 +1,105 @@
+mod context;
+mod runner;
+pub mod bots;
+
+pub use context::BotContext;
+pub use runner::{BotRunner, DEFAULT_JOIN_TIMEOUT};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use server::{ClientId, MessageId, RoomId};
+use std::time::Duration;
+
+// A chat bot driven by a BotRunner, which keeps it connected and calls the handlers one at a time.
+// A failing handler is logged and the bot keeps running
+#[async_trait]
+pub trait Bot: Send + Sync {
+    // Requested nickname
+    fn name(&self) -> &str;
+
+    // Messages starting with this go to `on_command` instead of `on_message`;
+    // '/' is taken by the server's slash commands
+    fn command_prefix(&self) -> &str {
+        "!"
+    }
+
+    // Each timer calls `on_timer` with its name, first after one period
+    fn timers(&self) -> Vec<Timer> {
+        Vec::new()
+    }
+
+    // Each time the bot gets into its room: after every connect and reconnect, and after being moved out
+    async fn on_connect(&self, _ctx: &BotContext) -> Result<()> {
+        Ok(())
+    }
+
+    // Chat from others in the bot's room
+    async fn on_message(&self, _ctx: &BotContext, _message: &ChatMessage) -> Result<()> {
+        Ok(())
+    }
+
+    async fn on_command(&self, _ctx: &BotContext, _command: &BotCommand) -> Result<()> {
+        Ok(())
+    }
+
+    // Someone else came online, in whichever room; the server announces connections to everyone,
+    // while moves between rooms only show up as System notices in those rooms
+    async fn on_online(&self, _ctx: &BotContext, _member: &Member) -> Result<()> {
+        Ok(())
+    }
+
+    async fn on_timer(&self, _ctx: &BotContext, _timer: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Timer {
+    pub name: String,
+    pub every: Duration,
+}
+
+impl Timer {
+    pub fn new(name: impl Into<String>, every: Duration) -> Self {
+        Self { name: name.into(), every }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    pub id: Option<MessageId>,
+    pub from: ClientId,
+    pub nickname: String,
+    pub content: String,
+    pub room: RoomId,
+    pub timestamp: i64,
+}
+
+// "!roll 2d6" is the command "roll" with args "2d6"
+#[derive(Debug, Clone)]
+pub struct BotCommand {
+    pub name: String,
+    pub args: String,
+    pub message: ChatMessage,
+}
+
+impl BotCommand {
+    pub(crate) fn parse(prefix: &str, message: &ChatMessage) -> Option<Self> {
+        let line = message.content.strip_prefix(prefix)?;
+        let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        if name.is_empty() {
+            return None;
+        }
+        Some(Self {
+            name: name.to_ascii_lowercase(),
+            args: args.trim().to_string(),
+            message: message.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub client_id: ClientId,
+    pub nickname: String,
+}
@@ -0,0 +1,176 @@
+use anyhow::{Result, bail};
+use client_lib::{ClientEvent, ClientHandle, ReconnectPolicy};
+use server::{ClientId, DEFAULT_ROOM, Message, PresenceEvent, RoomId};
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_stream::wrappers::IntervalStream;
+use tokio_stream::{StreamExt, StreamMap};
+
+use crate::{Bot, BotCommand, BotContext, ChatMessage, Member};
+
+pub const DEFAULT_JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Connects a bot, keeps it in its room across reconnects and feeds it events
+pub struct BotRunner {
+    address: String,
+    bot: Box<dyn Bot>,
+    client_id: Option<ClientId>,
+    token: Option<String>,
+    room: RoomId,
+    join_timeout: Duration,
+    reconnect: ReconnectPolicy,
+}
+
+impl BotRunner {
+    pub fn new(address: impl Into<String>, bot: impl Bot + 'static) -> Self {
+        Self {
+            address: address.into(),
+            bot: Box::new(bot),
+            client_id: None,
+            token: None,
+            room: DEFAULT_ROOM.to_string(),
+            join_timeout: DEFAULT_JOIN_TIMEOUT,
+            reconnect: ReconnectPolicy::backoff(Duration::from_secs(1), Duration::from_secs(60)),
+        }
+    }
+
+    // A fixed identity lets the bot keep roles granted to it
+    pub fn client_id(mut self, client_id: ClientId) -> Self {
+        self.client_id = Some(client_id);
+        self
+    }
+
+    // The token issued to `client_id` when it was first claimed
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    // Joined after every connect, the default room otherwise
+    pub fn room(mut self, room: impl Into<String>) -> Self {
+        self.room = room.into().trim_start_matches('#').to_ascii_lowercase();
+        self
+    }
+
+    // How long the server may take to move the bot to its room before `run` gives up
+    pub fn join_timeout(mut self, timeout: Duration) -> Self {
+        self.join_timeout = timeout;
+        self
+    }
+
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = policy;
+        self
+    }
+
+    // Runs until the connection is lost for good; fails right away if the first connect does,
+    // and whenever the bot's room can't be joined in time
+    pub async fn run(self) -> Result<()> {
+        let mut builder = ClientHandle::builder(self.address)
+            .nickname(self.bot.name())
+            .reconnect(self.reconnect);
+        if let Some(client_id) = self.client_id {
+            builder = builder.client_id(client_id);
+        }
+        if let Some(token) = &self.token {
+            builder = builder.token(token.clone());
+        }
+        let (client, mut events) = builder.connect().await?;
+        // A fixed identity connecting for the first time was just issued its token, which has to be kept
+        if self.client_id.is_some()
+            && self.token.is_none()
+            && let Some(token) = client.token().await
+        {
+            eprintln!("{} claimed {}, pass its token {} to connect as it again", self.bot.name(), client.client_id(), token);
+        }
+        let mut ctx = BotContext::new(client, DEFAULT_ROOM.to_string());
+
+        let mut timers = StreamMap::new();
+        for timer in self.bot.timers() {
+            let interval = tokio::time::interval_at(Instant::now() + timer.every, timer.every);
+            timers.insert(timer.name, IntervalStream::new(interval));
+        }
+
+        let bot = self.bot.as_ref();
+        // Timers only fire while the bot is in its room
+        let mut ready = false;
+        // Set while waiting for the server to move the bot to its room
+        let mut join_deadline: Option<Instant> = None;
+        loop {
+            tokio::select! {
+                event = events.next() => match event {
+                    Some(ClientEvent::Message(Message::RoomChanged { room, .. })) => {
+                        ctx.room = room;
+                        // Placed elsewhere on connecting, or moved out later, e.g. by a kick
+                        if ctx.room != self.room {
+                            ready = false;
+                            if join_deadline.is_none() {
+                                join_deadline = Some(Instant::now() + self.join_timeout);
+                                ctx.command(&format!("/join {}", self.room)).await?;
+                            }
+                            continue;
+                        }
+                        join_deadline = None;
+                        if let Ok(members) = ctx.client().who().await {
+                            ctx.nicknames.extend(members.into_iter().map(|member| (member.client_id, member.nickname)));
+                        }
+                        ready = true;
+                        report(bot, "on_connect", bot.on_connect(&ctx).await);
+                    }
+                    Some(ClientEvent::Message(message)) => dispatch(bot, &mut ctx, message).await,
+                    Some(ClientEvent::ConnectFailed { reason }) => eprintln!("Bot {} could not connect: {}", bot.name(), reason),
+                    Some(ClientEvent::Reconnecting { attempt, delay }) => {
+                        eprintln!("Bot {} lost its connection, reconnecting in {:?} (attempt {})", bot.name(), delay, attempt);
+                        ready = false;
+                        join_deadline = None;
+                    }
+                    Some(ClientEvent::Lagged { skipped }) => eprintln!("Bot {} missed {} events", bot.name(), skipped),
+                    Some(ClientEvent::Reconnected) => {}
+                    Some(ClientEvent::Disconnected) | None => bail!("Bot {} disconnected from the server", bot.name()),
+                },
+                _ = tokio::time::sleep_until(join_deadline.unwrap_or_else(Instant::now)), if join_deadline.is_some() => {
+                    bail!("Bot {} could not join #{} within {:?}", bot.name(), self.room, self.join_timeout);
+                }
+                Some((name, _)) = timers.next(), if ready => {
+                    report(bot, "on_timer", bot.on_timer(&ctx, &name).await);
+                }
+            }
+        }
+    }
+}
+
+async fn dispatch(bot: &dyn Bot, ctx: &mut BotContext, message: Message) {
+    match message {
+        Message::Chat { content, client_id, timestamp, id, room, .. } if client_id != ctx.own_id() => {
+            let message = ChatMessage {
+                id,
+                from: client_id,
+                nickname: ctx.nickname(&client_id),
+                content,
+                room,
+                timestamp,
+            };
+            match BotCommand::parse(bot.command_prefix(), &message) {
+                Some(command) => report(bot, "on_command", bot.on_command(ctx, &command).await),
+                None => report(bot, "on_message", bot.on_message(ctx, &message).await),
+            }
+        }
+        Message::Presence { client_id, nickname, event, .. } => {
+            ctx.nicknames.insert(client_id, nickname.clone());
+            if event == PresenceEvent::Joined && client_id != ctx.own_id() {
+                let member = Member { client_id, nickname };
+                report(bot, "on_online", bot.on_online(ctx, &member).await);
+            }
+        }
+        Message::NicknameChanged { client_id, nickname } => {
+            ctx.nicknames.insert(client_id, nickname);
+        }
+        _ => {}
+    }
+}
+
+fn report(bot: &dyn Bot, handler: &str, result: Result<()>) {
+    if let Err(e) = result {
+        eprintln!("Bot {} failed in {}: {}", bot.name(), handler, e);
+    }
+}
@@ -0,0 +1,217 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use bot::bots::{DeployNotifier, DiceRoller, FaqBot};
+use bot::{Bot, BotCommand, BotContext, BotRunner, ChatMessage, Timer};
+use client_lib::testing::{TestClient, TestServer};
+use server::{ClientId, Message, Role};
+use std::time::Duration;
+
+// Starts the bot and waits until it is online
+async fn start_bot(client: &mut TestClient, runner: BotRunner, name: &str) -> ClientId {
+    tokio::spawn(runner.run());
+    let joined = |message: &Message| matches!(message, Message::Presence { nickname, .. } if nickname == name);
+    let Message::Presence { client_id, .. } = client.expect_message(name, joined).await else {
+        unreachable!()
+    };
+    client_id
+}
+
+#[tokio::test]
+async fn dice_roller_answers_roll_commands() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect("alice").await;
+    let dice = start_bot(&mut alice, BotRunner::new(server.address(), DiceRoller::with_seed(7)), "dice").await;
+
+    alice.send_message("!roll 3d6").await.unwrap();
+    let answer = alice.expect_chat_from(dice).await;
+    let (rolls, total) = answer.strip_prefix("alice rolled 3d6: ").unwrap().split_once(" = ").unwrap();
+    let rolls: Vec<u32> = rolls.split(" + ").map(|roll| roll.parse().unwrap()).collect();
+    assert_eq!(rolls.len(), 3);
+    assert!(rolls.iter().all(|roll| (1..=6).contains(roll)));
+    assert_eq!(total.parse::<u32>().unwrap(), rolls.iter().sum::<u32>());
+
+    alice.send_message("!roll 0d1").await.unwrap();
+    assert_eq!(alice.expect_chat_from(dice).await, "Usage: !roll [N]dM, e.g. !roll 2d6");
+
+    // Other commands and plain chat are ignored, so the next answer is for the next roll
+    alice.send_message("!flip").await.unwrap();
+    alice.send_message("roll 2d6").await.unwrap();
+    alice.send_message("!ROLL d20").await.unwrap();
+    let answer = alice.expect_chat_from(dice).await;
+    let roll: u32 = answer.strip_prefix("alice rolled 1d20: ").unwrap().parse().unwrap();
+    assert!((1..=20).contains(&roll));
+}
+
+#[tokio::test]
+async fn faq_bot_answers_commands_and_questions_and_greets() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect("alice").await;
+    let faq = FaqBot::new()
+        .answer("deploys", "Deploys run at 10:00")
+        .answer("vpn", "Ask IT for a VPN key")
+        .greet(true);
+    let faq = start_bot(&mut alice, BotRunner::new(server.address(), faq), "faq").await;
+
+    alice.send_message("!faq vpn").await.unwrap();
+    assert_eq!(alice.expect_chat_from(faq).await, "Ask IT for a VPN key");
+
+    alice.send_message("!faq").await.unwrap();
+    assert_eq!(alice.expect_chat_from(faq).await, "Ask me about: deploys, vpn. Use !faq <topic>");
+
+    // Statements are left alone, questions about a topic are answered
+    alice.send_message("deploys are fun").await.unwrap();
+    alice.send_message("When do Deploys happen?").await.unwrap();
+    assert_eq!(alice.expect_chat_from(faq).await, "Deploys run at 10:00");
+
+    let _bob = server.connect("bob").await;
+    assert_eq!(
+        alice.expect_chat_from(faq).await,
+        "Welcome, bob! Type !faq for answers to common questions"
+    );
+}
+
+#[tokio::test]
+async fn deploy_notifier_announces_new_log_lines() {
+    let log = std::env::temp_dir().join(format!("deploys-{}.log", ClientId::new_v4().simple()));
+    std::fs::write(&log, "api v1.0 to production\n").unwrap();
+
+    let server = TestServer::start().await;
+    let mut alice = server.connect("alice").await;
+    let notifier = DeployNotifier::new(&log).poll_every(Duration::from_millis(50));
+    let deploys = start_bot(&mut alice, BotRunner::new(server.address(), notifier), "deploys").await;
+
+    // Deploys logged before the bot started are only remembered
+    alice.send_message("!lastdeploy").await.unwrap();
+    assert_eq!(alice.expect_chat_from(deploys).await, "Last deploy: api v1.0 to production");
+
+    std::fs::write(&log, "api v1.0 to production\napi v1.1 to staging\nweb v2.0 to production\n").unwrap();
+    assert_eq!(alice.expect_chat_from(deploys).await, "Deployed: api v1.1 to staging");
+    assert_eq!(alice.expect_chat_from(deploys).await, "Deployed: web v2.0 to production");
+
+    alice.send_message("!lastdeploy").await.unwrap();
+    assert_eq!(alice.expect_chat_from(deploys).await, "Last deploy: web v2.0 to production");
+    let _ = std::fs::remove_file(&log);
+}
+
+#[tokio::test]
+async fn runner_gives_up_on_a_room_it_cannot_join() {
+    let server = TestServer::start().await;
+    let echo = Echo { ticks: Default::default() };
+    let runner = BotRunner::new(server.address(), echo).room("no such room!").join_timeout(Duration::from_millis(200));
+
+    let error = tokio::time::timeout(Duration::from_secs(5), runner.run()).await.unwrap().unwrap_err();
+    assert!(error.to_string().contains("could not join #no such room!"), "{}", error);
+}
+
+#[tokio::test]
+async fn runner_goes_back_to_its_room_when_moved_out() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect("alice").await;
+    server.chat().assign_role(alice.client_id(), None, Role::Owner).await.unwrap();
+    alice.send_command("/join ops").await.unwrap();
+    alice
+        .expect_message("the move to #ops", |message| matches!(message, Message::RoomChanged { room, .. } if room == "ops"))
+        .await;
+
+    let echo = Echo { ticks: Default::default() };
+    let echo = start_bot(&mut alice, BotRunner::new(server.address(), echo).room("ops"), "echo").await;
+    assert_eq!(alice.expect_chat_from(echo).await, "hello #ops");
+
+    alice.send_command("/kick echo").await.unwrap();
+    assert_eq!(alice.expect_chat_from(echo).await, "hello #ops");
+    assert_eq!(server.chat().current_room(&echo).await.as_deref(), Some("ops"));
+}
+
+#[tokio::test]
+async fn deploy_notifier_starts_from_the_first_log_it_can_read() {
+    let log = std::env::temp_dir().join(format!("deploys-{}.log", ClientId::new_v4().simple()));
+    // Reading a directory fails, unlike a missing file
+    std::fs::create_dir(&log).unwrap();
+
+    let server = TestServer::start().await;
+    let mut alice = server.connect("alice").await;
+    let notifier = DeployNotifier::new(&log).poll_every(Duration::from_millis(50));
+    let deploys = start_bot(&mut alice, BotRunner::new(server.address(), notifier), "deploys").await;
+
+    std::fs::remove_dir(&log).unwrap();
+    std::fs::write(&log, "api v1.0 to production\nweb v1.0 to production\n").unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    std::fs::write(&log, "api v1.0 to production\nweb v1.0 to production\napi v1.1 to staging\n").unwrap();
+    assert_eq!(alice.expect_chat_from(deploys).await, "Deployed: api v1.1 to staging");
+    let _ = std::fs::remove_file(&log);
+}
+
+// Says hello when ready, echoes commands with its own prefix and counts timer ticks on request
+struct Echo {
+    ticks: std::sync::atomic::AtomicU32,
+}
+
+#[async_trait]
+impl Bot for Echo {
+    fn name(&self) -> &str {
+        "echo"
+    }
+
+    fn command_prefix(&self) -> &str {
+        "echo:"
+    }
+
+    fn timers(&self) -> Vec<Timer> {
+        vec![Timer::new("tick", Duration::from_millis(20))]
+    }
+
+    async fn on_connect(&self, ctx: &BotContext) -> Result<()> {
+        ctx.say(&format!("hello #{}", ctx.room())).await
+    }
+
+    async fn on_message(&self, ctx: &BotContext, message: &ChatMessage) -> Result<()> {
+        if message.content == "ticks?" {
+            let ticks = self.ticks.load(std::sync::atomic::Ordering::SeqCst);
+            return ctx.say(if ticks > 0 { "ticking" } else { "no ticks" }).await;
+        }
+        Ok(())
+    }
+
+    async fn on_command(&self, ctx: &BotContext, command: &BotCommand) -> Result<()> {
+        if command.name == "fail" {
+            anyhow::bail!("asked to fail");
+        }
+        ctx.reply(&command.message, &format!("{} {} from {}", command.name, command.args, command.message.nickname))
+            .await
+    }
+
+    async fn on_timer(&self, _ctx: &BotContext, timer: &str) -> Result<()> {
+        assert_eq!(timer, "tick");
+        self.ticks.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn runner_joins_its_room_and_dispatches_handlers() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect("alice").await;
+    alice.send_command("/join ops").await.unwrap();
+    alice
+        .expect_message("the move to #ops", |message| matches!(message, Message::RoomChanged { room, .. } if room == "ops"))
+        .await;
+
+    let echo = Echo { ticks: Default::default() };
+    let echo = start_bot(&mut alice, BotRunner::new(server.address(), echo).room("#Ops"), "echo").await;
+    assert_eq!(alice.expect_chat_from(echo).await, "hello #ops");
+
+    // A failing handler is reported and the bot carries on
+    alice.send_message("echo:fail").await.unwrap();
+    alice.send_message("echo:say hi there").await.unwrap();
+    let reply = alice
+        .expect_message("the echo", |message| matches!(message, Message::Chat { client_id, .. } if *client_id == echo))
+        .await;
+    let Message::Chat { content, reply_to, room, .. } = reply else { unreachable!() };
+    assert_eq!(content, "say hi there from alice");
+    assert_eq!(room, "ops");
+    assert!(reply_to.is_some());
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    alice.send_message("ticks?").await.unwrap();
+    assert_eq!(alice.expect_chat_from(echo).await, "ticking");
+}
@@ -0,0 +1,272 @@
+use anyhow::{Result, anyhow, bail};
+use client_lib::{ClientBuilder, ClientError, ClientEvent, ClientHandle, EventStream, ReconnectPolicy, clock, display_name};
+use server::{ClientId, DEFAULT_ROOM, Message, PresenceEvent, UserStatus};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio_stream::StreamExt;
+
+const USAGE: &str = "Usage:
+  chat-cli [OPTIONS] send [--to ROOM] [TEXT]...
+  chat-cli [OPTIONS] tail [--room ROOM] [--json]
+  chat-cli [OPTIONS] who [--json]
+
+Options:
+  --address HOST:PORT  server to connect to (default CHAT_ADDRESS or 127.0.0.1:8080)
+  --nickname NAME      nickname to connect with (default CHAT_NICKNAME)
+  --client-id ID       identity to connect as (default CHAT_CLIENT_ID)
+  --token TOKEN        token the server issued for that identity (default CHAT_TOKEN)
+
+`send` without TEXT sends each line read from stdin as its own message; lines the server
+refuses are reported with the reason and the rest are still sent.
+Text is always sent as chat, a leading '/' does not run a command";
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+// How long the server gets to move us to the requested room
+const JOIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Options {
+    address: String,
+    nickname: Option<String>,
+    client_id: Option<ClientId>,
+    token: Option<String>,
+}
+
+enum Command {
+    Send { to: Option<String>, text: Vec<String> },
+    Tail { room: Option<String>, json: bool },
+    Who { json: bool },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let (options, command) = parse_args(std::env::args().skip(1))?;
+    match command {
+        Command::Send { to, text } => send(&options, to.as_deref(), text).await,
+        Command::Tail { room, json } => tail(&options, room.as_deref(), json).await,
+        Command::Who { json } => who(&options, json).await,
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(Options, Command)> {
+    let mut options = Options {
+        address: std::env::var("CHAT_ADDRESS").unwrap_or_else(|_| DEFAULT_ADDRESS.to_string()),
+        nickname: std::env::var("CHAT_NICKNAME").ok(),
+        client_id: std::env::var("CHAT_CLIENT_ID").ok().and_then(|id| id.parse().ok()),
+        token: std::env::var("CHAT_TOKEN").ok(),
+    };
+
+    let subcommand = loop {
+        let Some(arg) = args.next() else {
+            bail!("{}", USAGE);
+        };
+        match arg.as_str() {
+            "--address" => options.address = value(&arg, &mut args)?,
+            "--nickname" => options.nickname = Some(value(&arg, &mut args)?),
+            "--client-id" => options.client_id = Some(value(&arg, &mut args)?.parse()?),
+            "--token" => options.token = Some(value(&arg, &mut args)?),
+            "-h" | "--help" => bail!("{}", USAGE),
+            _ => break arg,
+        }
+    };
+
+    let mut room = None;
+    let mut json = false;
+    let mut text = Vec::new();
+    while let Some(arg) = args.next() {
+        match (subcommand.as_str(), arg.as_str()) {
+            ("send", "--to") | ("tail", "--room") => room = Some(value(&arg, &mut args)?),
+            ("tail" | "who", "--json") => json = true,
+            ("send", _) if !arg.starts_with("--") => text.push(arg),
+            _ => bail!("Unknown option {} for {}\n\n{}", arg, subcommand, USAGE),
+        }
+    }
+
+    let command = match subcommand.as_str() {
+        "send" => Command::Send { to: room, text },
+        "tail" => Command::Tail { room, json },
+        "who" => Command::Who { json },
+        _ => bail!("Unknown command {}\n\n{}", subcommand, USAGE),
+    };
+    Ok((options, command))
+}
+
+fn value(flag: &str, args: &mut impl Iterator<Item = String>) -> Result<String> {
+    args.next().ok_or_else(|| anyhow!("Missing value for {}\n\n{}", flag, USAGE))
+}
+
+fn builder(options: &Options) -> ClientBuilder {
+    let mut builder = ClientHandle::builder(options.address.clone());
+    if let Some(nickname) = &options.nickname {
+        builder = builder.nickname(nickname.clone());
+    }
+    if let Some(client_id) = options.client_id {
+        builder = builder.client_id(client_id);
+    }
+    if let Some(token) = &options.token {
+        builder = builder.token(token.clone());
+    }
+    builder
+}
+
+// A fixed identity connecting for the first time was just issued its token, which has to be kept
+async fn connect(options: &Options, builder: ClientBuilder) -> Result<ClientHandle> {
+    let (client, _) = builder.connect().await?;
+    if options.client_id.is_some()
+        && options.token.is_none()
+        && let Some(token) = client.token().await
+    {
+        eprintln!("Claimed {}, pass --token {} to connect as it again", client.client_id(), token);
+    }
+    Ok(client)
+}
+
+// Every client starts in the default room, so only other rooms need a /join
+async fn join(client: &ClientHandle, events: &mut EventStream, room: &str) -> Result<()> {
+    let room = room.trim().trim_start_matches('#').to_ascii_lowercase();
+    if room == DEFAULT_ROOM {
+        return Ok(());
+    }
+    client.send_command(&format!("/join {}", room)).await?;
+
+    // A refused join only shows up as a System message, reported if the move never comes
+    let mut last_notice = None;
+    let joined = tokio::time::timeout(JOIN_TIMEOUT, async {
+        while let Some(event) = events.next().await {
+            match event {
+                ClientEvent::Message(Message::RoomChanged { room: joined, .. }) if joined == room => return true,
+                ClientEvent::Message(Message::System { content }) => last_notice = Some(content),
+                _ => {}
+            }
+        }
+        false
+    })
+    .await;
+
+    match (joined, last_notice) {
+        (Ok(true), _) => Ok(()),
+        (_, Some(notice)) => bail!("Could not join #{}: {}", room, notice),
+        (Ok(false), None) => bail!("Disconnected before joining #{}", room),
+        (Err(_), None) => bail!("Timed out joining #{}", room),
+    }
+}
+
+async fn send(options: &Options, to: Option<&str>, text: Vec<String>) -> Result<()> {
+    let client = connect(options, builder(options)).await?;
+    if let Some(room) = to {
+        join(&client, &mut client.subscribe(), room).await?;
+    }
+
+    if !text.is_empty() {
+        send_line(&client, &text.join(" ")).await?;
+        return Ok(());
+    }
+    let mut rejected = 0;
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match send_line(&client, &line).await {
+            Err(ClientError::MessageRejected(reason)) => {
+                eprintln!("Not sent ({}): {}", reason, line);
+                rejected += 1;
+            }
+            result => result?,
+        }
+    }
+    if rejected > 0 {
+        bail!("The server rejected {} line(s)", rejected);
+    }
+    Ok(())
+}
+
+// Waits for the server's Ack, so nothing is lost when the process exits right after.
+// A refused line fails with `ClientError::MessageRejected` and the server's reason
+async fn send_line(client: &ClientHandle, line: &str) -> Result<(), ClientError> {
+    // The server treats a leading "//" as a literal slash
+    let line = if line.starts_with('/') { format!("/{}", line) } else { line.to_string() };
+    client.send_message(&line).await?.accepted().await?;
+    Ok(())
+}
+
+async fn tail(options: &Options, room: Option<&str>, json: bool) -> Result<()> {
+    let backoff = ReconnectPolicy::backoff(Duration::from_secs(1), Duration::from_secs(30));
+    let client = connect(options, builder(options).reconnect(backoff)).await?;
+    let mut events = client.subscribe();
+    if let Some(room) = room {
+        join(&client, &mut events, room).await?;
+    }
+
+    let mut nicknames: HashMap<ClientId, String> = HashMap::new();
+    if !json {
+        for member in client.who().await? {
+            nicknames.insert(member.client_id, member.nickname);
+        }
+    }
+
+    while let Some(event) = events.next().await {
+        match event {
+            ClientEvent::Message(message) if json => println!("{}", serde_json::to_string(&message)?),
+            ClientEvent::Message(message) => print_message(&message, &mut nicknames),
+            // The client rejoins the room it was in on its own
+            ClientEvent::Reconnected => eprintln!("Reconnected"),
+            ClientEvent::ConnectFailed { reason } => eprintln!("Could not connect: {}", reason),
+            ClientEvent::Reconnecting { attempt, delay } => {
+                eprintln!("Connection lost, reconnecting in {:?} (attempt {})", delay, attempt);
+            }
+            ClientEvent::Lagged { skipped } => eprintln!("Missed {} messages", skipped),
+            ClientEvent::Disconnected => break,
+        }
+    }
+    bail!("Disconnected from the server")
+}
+
+fn print_message(message: &Message, nicknames: &mut HashMap<ClientId, String>) {
+    let name = |nicknames: &HashMap<ClientId, String>, client_id: &ClientId| {
+        display_name(nicknames.get(client_id).map(String::as_str), client_id)
+    };
+    match message {
+        Message::Chat { content, client_id, timestamp, room, .. } => {
+            println!("{} #{} {}: {}", clock(*timestamp), room, name(nicknames, client_id), content);
+        }
+        Message::System { content } => println!("* {}", content),
+        Message::Presence { client_id, nickname, event, .. } => {
+            nicknames.insert(*client_id, nickname.clone());
+            let verb = match event {
+                PresenceEvent::Joined => "joined",
+                PresenceEvent::Left => "left",
+            };
+            println!("* {} {}", nickname, verb);
+        }
+        Message::NicknameChanged { client_id, nickname } => {
+            println!("* {} is now known as {}", name(nicknames, client_id), nickname);
+            nicknames.insert(*client_id, nickname.clone());
+        }
+        _ => {}
+    }
+}
+
+async fn who(options: &Options, json: bool) -> Result<()> {
+    let client = connect(options, builder(options)).await?;
+    let mut members = client.who().await?;
+    members.sort_by_key(|member| member.nickname.to_ascii_lowercase());
+
+    for member in members {
+        if json {
+            println!("{}", serde_json::to_string(&member)?);
+            continue;
+        }
+        let status = match member.status {
+            UserStatus::Online => "online",
+            UserStatus::Away => "away",
+            UserStatus::Busy => "busy",
+            UserStatus::Invisible => "invisible",
+        };
+        match member.status_text {
+            Some(text) => println!("{}\t{}\t{}", member.nickname, status, text),
+            None => println!("{}\t{}", member.nickname, status),
+        }
+    }
+    Ok(())
+}
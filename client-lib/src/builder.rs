@@ -0,0 +1,249 @@
+use server::ClientId;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::{Handle, Runtime};
+use uuid::Uuid;
+
+use crate::ClientHandle;
+use crate::connection::{self, ClientConfig, SessionState, SharedSession};
+use crate::error::{ClientError, Result};
+use crate::events::{EventBus, EventStream};
+#[cfg(feature = "tls")]
+use crate::tls::TlsSettings;
+
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// Messages queued for sending before `send` starts waiting
+pub const DEFAULT_OUTGOING_CAPACITY: usize = 100;
+// Events buffered per subscriber before the slowest one starts lagging
+pub const DEFAULT_EVENT_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Default)]
+pub enum ReconnectPolicy {
+    #[default]
+    Never,
+    // Waits `initial_delay` before the first retry, doubling up to `max_delay`.
+    // `max_attempts: None` keeps retrying for as long as a handle is alive
+    Backoff {
+        initial_delay: Duration,
+        max_delay: Duration,
+        max_attempts: Option<u32>,
+    },
+}
+
+impl ReconnectPolicy {
+    pub fn backoff(initial_delay: Duration, max_delay: Duration) -> Self {
+        ReconnectPolicy::Backoff { initial_delay, max_delay, max_attempts: None }
+    }
+
+    // Delay before reconnect attempt `attempt` (starting at 1), or None to give up
+    pub(crate) fn delay(&self, attempt: u32) -> Option<Duration> {
+        match self {
+            ReconnectPolicy::Never => None,
+            ReconnectPolicy::Backoff { initial_delay, max_delay, max_attempts } => {
+                if max_attempts.is_some_and(|max| attempt > max) {
+                    return None;
+                }
+                let factor = 2u32.saturating_pow(attempt.saturating_sub(1).min(16));
+                Some(initial_delay.saturating_mul(factor).min(*max_delay))
+            }
+        }
+    }
+}
+
+// A runtime created for clients built outside of one; shut down in the background
+// so the last handle can be dropped from async code as well
+struct OwnedRuntime(Option<Runtime>);
+
+impl Drop for OwnedRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+// Where the client's tasks run
+#[derive(Clone)]
+pub(crate) struct ClientRuntime {
+    pub(crate) handle: Handle,
+    _owned: Option<Arc<OwnedRuntime>>,
+}
+
+pub struct ClientBuilder {
+    address: String,
+    client_id: Option<ClientId>,
+    token: Option<String>,
+    nickname: Option<String>,
+    echo: bool,
+    connect_timeout: Duration,
+    outgoing_capacity: usize,
+    event_capacity: usize,
+    heartbeat_interval: Option<Duration>,
+    reconnect: ReconnectPolicy,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsSettings>,
+    runtime: Option<Handle>,
+}
+
+impl ClientBuilder {
+    pub fn new(address: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            client_id: None,
+            token: None,
+            nickname: None,
+            echo: false,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            outgoing_capacity: DEFAULT_OUTGOING_CAPACITY,
+            event_capacity: DEFAULT_EVENT_CAPACITY,
+            heartbeat_interval: None,
+            reconnect: ReconnectPolicy::Never,
+            #[cfg(feature = "tls")]
+            tls: None,
+            runtime: None,
+        }
+    }
+
+    // The server knows a user by this id across connections, e.g. for roles and bans.
+    // A random one is used when unset
+    pub fn client_id(mut self, client_id: ClientId) -> Self {
+        self.client_id = Some(client_id);
+        self
+    }
+
+    // The token the server issued for `client_id`, see `ClientHandle::token`.
+    // Needed to connect as an id that has connected before
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    // Requested display name, the server falls back to a short form of the id if invalid or taken
+    pub fn nickname(mut self, nickname: impl Into<String>) -> Self {
+        self.nickname = Some(nickname.into());
+        self
+    }
+
+    // Ask the server to send our own chat messages back, in broadcast order
+    pub fn echo(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    // Covers both the TCP connect and registration, for each attempt
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn outgoing_capacity(mut self, capacity: usize) -> Self {
+        self.outgoing_capacity = capacity.max(1);
+        self
+    }
+
+    pub fn event_capacity(mut self, capacity: usize) -> Self {
+        self.event_capacity = capacity.max(1);
+        self
+    }
+
+    // Sends a heartbeat this often so dead connections are noticed even when idle
+    pub fn heartbeat_interval(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = Some(interval);
+        self
+    }
+
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = policy;
+        self
+    }
+
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, settings: TlsSettings) -> Self {
+        self.tls = Some(settings);
+        self
+    }
+
+    // Runs the client's tasks on `handle`. Without one the current runtime is used,
+    // or a small runtime owned by the client when there is none
+    pub fn runtime(mut self, handle: Handle) -> Self {
+        self.runtime = Some(handle);
+        self
+    }
+
+    // Resolves once the server has registered the client, or fails with the reason it could not.
+    // The returned stream sees every event from the start of the connection,
+    // further independent subscribers can be added with `ClientHandle::subscribe`
+    pub async fn connect(self) -> Result<(ClientHandle, EventStream)> {
+        let (setup, first_subscriber) = self.prepare()?;
+
+        // Connecting needs Tokio's reactor, which the caller's executor might not provide
+        let config = setup.config.clone();
+        let events = setup.events.clone();
+        let session = setup.session.clone();
+        let connection = setup
+            .runtime
+            .handle
+            .spawn(async move { connection::establish(&config, &events, &session).await })
+            .await
+            .map_err(|_| ClientError::Disconnected)??;
+
+        Ok((ClientHandle::start(setup, Some(connection)), first_subscriber))
+    }
+
+    // Returns immediately and connects in the background, for callers that can't wait.
+    // A failed connection shows up as `ClientEvent::ConnectFailed`, then `Reconnecting` or `Disconnected`
+    // as the reconnect policy decides, and sends fail with `ClientError::Disconnected`
+    pub fn connect_in_background(self) -> Result<(ClientHandle, EventStream)> {
+        let (setup, first_subscriber) = self.prepare()?;
+        Ok((ClientHandle::start(setup, None), first_subscriber))
+    }
+
+    fn prepare(self) -> Result<(ClientSetup, EventStream)> {
+        let runtime = match self.runtime.or_else(|| Handle::try_current().ok()) {
+            Some(handle) => ClientRuntime { handle, _owned: None },
+            None => {
+                let runtime = tokio::runtime::Builder::new_multi_thread()
+                    .worker_threads(1)
+                    .thread_name("chat-client")
+                    .enable_all()
+                    .build()?;
+                ClientRuntime {
+                    handle: runtime.handle().clone(),
+                    _owned: Some(Arc::new(OwnedRuntime(Some(runtime)))),
+                }
+            }
+        };
+
+        let config = ClientConfig {
+            address: self.address,
+            client_id: self.client_id.unwrap_or_else(Uuid::new_v4),
+            nickname: self.nickname,
+            echo: self.echo,
+            connect_timeout: self.connect_timeout,
+            heartbeat_interval: self.heartbeat_interval,
+            reconnect: self.reconnect,
+            #[cfg(feature = "tls")]
+            tls: self.tls,
+        };
+        let events = EventBus::new(self.event_capacity);
+        let first_subscriber = events.subscribe();
+        let session: SharedSession = Arc::new(tokio::sync::Mutex::new(SessionState::new(self.token)));
+
+        let setup = ClientSetup {
+            runtime,
+            config,
+            events,
+            session,
+            outgoing_capacity: self.outgoing_capacity,
+        };
+        Ok((setup, first_subscriber))
+    }
+}
+
+pub(crate) struct ClientSetup {
+    pub(crate) runtime: ClientRuntime,
+    pub(crate) config: ClientConfig,
+    pub(crate) events: EventBus,
+    pub(crate) session: SharedSession,
+    pub(crate) outgoing_capacity: usize,
+}
@@ -0,0 +1,341 @@
+use server::{ClientId, ClientMsgId, DEFAULT_ROOM, Message, RequestId, RoomId};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::{Mutex, mpsc};
+use tokio::time::{Instant, Interval};
+
+use crate::builder::ReconnectPolicy;
+use crate::delivery::{Delivery, PendingAcks, PendingRequests};
+use crate::error::{ClientError, Result};
+use crate::events::{ClientEvent, EventBus};
+use crate::request::ClientRequest;
+#[cfg(feature = "tls")]
+use crate::tls::TlsSettings;
+
+// Everything the connection task needs to (re)connect, fixed when the client is built
+#[derive(Clone)]
+pub(crate) struct ClientConfig {
+    pub(crate) address: String,
+    pub(crate) client_id: ClientId,
+    pub(crate) nickname: Option<String>,
+    pub(crate) echo: bool,
+    pub(crate) connect_timeout: Duration,
+    pub(crate) heartbeat_interval: Option<Duration>,
+    pub(crate) reconnect: ReconnectPolicy,
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<TlsSettings>,
+}
+
+pub(crate) trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
+pub(crate) type BoxedTransport = Box<dyn Transport>;
+
+// A registered connection, ready for the session
+pub(crate) struct Connection {
+    reader: BufReader<ReadHalf<BoxedTransport>>,
+    writer: WriteHalf<BoxedTransport>,
+}
+
+// What the server last told this client about itself, shared with every handle
+pub(crate) struct SessionState {
+    pub(crate) room: RoomId,
+    // Presented at every registration, the server issues one on the first
+    pub(crate) token: Option<String>,
+    // The last SetStatus sent, replayed on a new connection
+    pub(crate) status: Option<Message>,
+}
+
+impl SessionState {
+    pub(crate) fn new(token: Option<String>) -> Self {
+        Self { room: DEFAULT_ROOM.to_string(), token, status: None }
+    }
+}
+
+pub(crate) type SharedSession = Arc<Mutex<SessionState>>;
+
+// Ids of the messages written to the current connection that still wait for an Ack or Response
+#[derive(Default)]
+struct InFlight {
+    acks: Vec<ClientMsgId>,
+    requests: Vec<RequestId>,
+}
+
+impl InFlight {
+    fn record(&mut self, message: &Message) {
+        match message {
+            Message::Chat { client_msg_id: Some(client_msg_id), .. } => self.acks.push(*client_msg_id),
+            Message::Request { request_id, .. } => self.requests.push(*request_id),
+            _ => {}
+        }
+    }
+
+    // Answers to these never arrive once the connection is lost, dropping the senders fails
+    // their waiters with Disconnected
+    async fn fail(self, pending_acks: &PendingAcks, pending_requests: &PendingRequests) {
+        let mut acks = pending_acks.lock().await;
+        for client_msg_id in self.acks {
+            acks.remove(&client_msg_id);
+        }
+        let mut requests = pending_requests.lock().await;
+        for request_id in self.requests {
+            requests.remove(&request_id);
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum SessionEnd {
+    // Every handle was dropped
+    Closed,
+    Lost,
+}
+
+pub(crate) async fn establish(config: &ClientConfig, events: &EventBus, session: &SharedSession) -> Result<Connection> {
+    tokio::time::timeout(config.connect_timeout, register(config, events, session))
+        .await
+        .map_err(|_| ClientError::ConnectTimeout {
+            address: config.address.clone(),
+            timeout: config.connect_timeout,
+        })?
+}
+
+async fn open_transport(config: &ClientConfig) -> Result<BoxedTransport> {
+    let stream = TcpStream::connect(&config.address).await.map_err(|source| ClientError::Connect {
+        address: config.address.clone(),
+        source,
+    })?;
+    #[cfg(feature = "tls")]
+    if let Some(tls) = &config.tls {
+        return crate::tls::wrap(stream, &config.address, tls).await;
+    }
+    Ok(Box::new(stream))
+}
+
+// The server places every client it accepts in a room, so the first RoomChanged completes registration
+async fn register(config: &ClientConfig, events: &EventBus, session: &SharedSession) -> Result<Connection> {
+    let (read_stream, mut writer) = tokio::io::split(open_transport(config).await?);
+    let mut reader = BufReader::new(read_stream);
+
+    let token = session.lock().await.token.clone();
+    let log = Message::log(config.client_id, config.echo, config.nickname.as_deref(), token.as_deref());
+    send_message_to_server(&mut writer, log).await?;
+
+    // Messages sent before that, like the MOTD or the reason for a refusal, still reach subscribers
+    let mut last_notice = None;
+    let mut line_buffer = String::new();
+    loop {
+        line_buffer.clear();
+        if reader.read_line(&mut line_buffer).await? == 0 {
+            let reason = last_notice.unwrap_or_else(|| "the server closed the connection".to_string());
+            return Err(ClientError::Rejected(reason));
+        }
+        let Ok(message) = serde_json::from_str::<Message>(line_buffer.trim()) else {
+            continue;
+        };
+        let registered = matches!(message, Message::RoomChanged { .. });
+        match &message {
+            Message::System { content } => last_notice = Some(content.clone()),
+            Message::Credentials { token } => session.lock().await.token = Some(token.clone()),
+            _ => {}
+        }
+        events.publish(ClientEvent::Message(message));
+        if registered {
+            return Ok(Connection { reader, writer });
+        }
+    }
+}
+
+// Drives the connection until every handle is dropped or the reconnect policy gives up.
+// `initial` is an already registered connection, otherwise the first one is made here
+pub(crate) async fn run(
+    config: ClientConfig,
+    initial: Option<Connection>,
+    mut outgoing_rx: mpsc::Receiver<ClientRequest>,
+    events: EventBus,
+    pending_acks: PendingAcks,
+    pending_requests: PendingRequests,
+    session: SharedSession,
+) {
+    let mut next = initial;
+    let mut attempt = 0;
+    loop {
+        let connection = match next.take() {
+            Some(connection) => Ok(connection),
+            None => establish(&config, &events, &session).await,
+        };
+        match connection {
+            Ok(mut connection) => {
+                if attempt > 0 {
+                    // A failed write shows up as a lost session right away
+                    let _ = restore(&config, &mut connection, &session).await;
+                    events.publish(ClientEvent::Reconnected);
+                }
+                attempt = 0;
+                let (end, in_flight) = run_session(
+                    &config,
+                    connection,
+                    &mut outgoing_rx,
+                    &events,
+                    &pending_acks,
+                    &pending_requests,
+                    &session,
+                )
+                .await;
+                // Messages still queued keep waiting, they are written to the next connection
+                in_flight.fail(&pending_acks, &pending_requests).await;
+                if end == SessionEnd::Closed {
+                    break;
+                }
+            }
+            Err(e) => events.publish(ClientEvent::ConnectFailed { reason: e.to_string() }),
+        }
+
+        attempt += 1;
+        let Some(delay) = config.reconnect.delay(attempt) else {
+            break;
+        };
+        if outgoing_rx.is_closed() {
+            break;
+        }
+        events.publish(ClientEvent::Reconnecting { attempt, delay });
+        tokio::time::sleep(delay).await;
+    }
+    events.close();
+}
+
+// The server puts a new connection online in the default room, so ask for what the previous one had.
+// The room is the default one until the server confirms the join
+async fn restore(config: &ClientConfig, connection: &mut Connection, session: &SharedSession) -> Result<()> {
+    let (room, status) = {
+        let mut session = session.lock().await;
+        (std::mem::replace(&mut session.room, DEFAULT_ROOM.to_string()), session.status.clone())
+    };
+    if let Some(status) = status {
+        send_message_to_server(&mut connection.writer, status).await?;
+    }
+    if room != DEFAULT_ROOM {
+        let join = Message::chat(config.client_id, &format!("/join {}", room));
+        send_message_to_server(&mut connection.writer, join).await?;
+    }
+    Ok(())
+}
+
+async fn run_session(
+    config: &ClientConfig,
+    connection: Connection,
+    outgoing_rx: &mut mpsc::Receiver<ClientRequest>,
+    events: &EventBus,
+    pending_acks: &PendingAcks,
+    pending_requests: &PendingRequests,
+    session: &SharedSession,
+) -> (SessionEnd, InFlight) {
+    let Connection { reader, mut writer } = connection;
+    let mut in_flight = InFlight::default();
+
+    // Spawn task to handle incoming messages
+    let mut incoming_task = spawn_incoming_handler(
+        reader,
+        events.clone(),
+        pending_acks.clone(),
+        pending_requests.clone(),
+        session.clone(),
+    );
+    let mut heartbeat = config.heartbeat_interval.map(|period| tokio::time::interval_at(Instant::now() + period, period));
+
+    // Outgoing messages are written from here so unsent ones stay queued for the next connection
+    let end = loop {
+        tokio::select! {
+            _ = &mut incoming_task => break SessionEnd::Lost,
+            request = outgoing_rx.recv() => match request {
+                Some(request) => {
+                    let message = request.into_message(config.client_id);
+                    if matches!(message, Message::SetStatus { .. }) {
+                        session.lock().await.status = Some(message.clone());
+                    }
+                    in_flight.record(&message);
+                    if send_message_to_server(&mut writer, message).await.is_err() {
+                        break SessionEnd::Lost;
+                    }
+                }
+                None => break SessionEnd::Closed,
+            },
+            _ = next_heartbeat(&mut heartbeat) => {
+                if send_message_to_server(&mut writer, Message::heartbeat()).await.is_err() {
+                    break SessionEnd::Lost;
+                }
+            }
+        }
+    };
+    incoming_task.abort();
+    (end, in_flight)
+}
+
+async fn next_heartbeat(heartbeat: &mut Option<Interval>) {
+    match heartbeat {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+fn spawn_incoming_handler(
+    mut reader: BufReader<ReadHalf<BoxedTransport>>,
+    events: EventBus,
+    pending_acks: PendingAcks,
+    pending_requests: PendingRequests,
+    session: SharedSession,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut line_buffer = String::new();
+
+        loop {
+            line_buffer.clear();
+            match reader.read_line(&mut line_buffer).await {
+                Ok(0) => break, // Connection closed
+                Ok(_) => {
+                    let message_text = line_buffer.trim();
+                    match serde_json::from_str::<Message>(message_text) {
+                        Ok(Message::Ack { client_msg_id, server_msg_id, timestamp }) => {
+                            if let Some(ack_tx) = pending_acks.lock().await.remove(&client_msg_id) {
+                                let _ = ack_tx.send(Ok(Delivery { client_msg_id, server_msg_id, timestamp }));
+                            }
+                        }
+                        // Also published, for clients that show the reason without awaiting the delivery
+                        Ok(Message::Nack { client_msg_id, reason }) => {
+                            if let Some(ack_tx) = pending_acks.lock().await.remove(&client_msg_id) {
+                                let _ = ack_tx.send(Err(ClientError::MessageRejected(reason.clone())));
+                            }
+                            events.publish(ClientEvent::Message(Message::Nack { client_msg_id, reason }));
+                        }
+                        Ok(Message::Response { request_id, response }) => {
+                            if let Some(response_tx) = pending_requests.lock().await.remove(&request_id) {
+                                let _ = response_tx.send(*response);
+                            }
+                        }
+                        Ok(message) => {
+                            if let Message::RoomChanged { room, .. } = &message {
+                                session.lock().await.room = room.clone();
+                            }
+                            events.publish(ClientEvent::Message(message));
+                        }
+                        Err(_) => {}
+                    }
+                }
+                Err(_) => break, // Connection error
+            }
+        }
+    })
+}
+
+async fn send_message_to_server(writer: &mut WriteHalf<BoxedTransport>, message: Message) -> Result<()> {
+    let json = serde_json::to_string(&message)?;
+    let message_with_newline = format!("{}\n", json);
+    writer.write_all(message_with_newline.as_bytes()).await?;
+    writer.flush().await?;
+    Ok(())
+}
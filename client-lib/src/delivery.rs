@@ -0,0 +1,45 @@
+use server::{ClientMsgId, Message, MessageId, RequestId};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, oneshot};
+use crate::error::{ClientError, Result};
+
+pub const ACK_TIMEOUT: Duration = Duration::from_secs(10);
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Chat messages waiting for the server's Ack, or its Nack with the reason
+pub(crate) type PendingAcks = Arc<Mutex<HashMap<ClientMsgId, oneshot::Sender<Result<Delivery>>>>>;
+// Requests waiting for the Response with their id
+pub(crate) type PendingRequests = Arc<Mutex<HashMap<RequestId, oneshot::Sender<Message>>>>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Delivery {
+    pub client_msg_id: ClientMsgId,
+    pub server_msg_id: MessageId,
+    pub timestamp: i64,
+}
+
+// A chat message queued for sending, resolves once the server acknowledges it
+pub struct PendingMessage {
+    pub client_msg_id: ClientMsgId,
+    ack: oneshot::Receiver<Result<Delivery>>,
+    pending: PendingAcks,
+}
+
+impl PendingMessage {
+    pub(crate) fn new(client_msg_id: ClientMsgId, ack: oneshot::Receiver<Result<Delivery>>, pending: PendingAcks) -> Self {
+        Self { client_msg_id, ack, pending }
+    }
+
+    // Fails with `ClientError::MessageRejected` and the server's reason if the message was not posted
+    pub async fn accepted(self) -> Result<Delivery> {
+        match tokio::time::timeout(ACK_TIMEOUT, self.ack).await {
+            Ok(outcome) => outcome.map_err(|_| ClientError::Disconnected)?,
+            Err(_) => {
+                self.pending.lock().await.remove(&self.client_msg_id);
+                Err(ClientError::AckTimeout)
+            }
+        }
+    }
+}
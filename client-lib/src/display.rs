@@ -0,0 +1,20 @@
+use server::ClientId;
+use server::mentions::default_nickname;
+
+// How every client names people and shows times, so they read the same in all of them
+
+// The first 8 hex digits of the id, which is also the nickname the server falls back to
+pub fn short_id(client_id: &ClientId) -> String {
+    default_nickname(client_id)
+}
+
+// The nickname if it is known, the short id otherwise
+pub fn display_name(nickname: Option<&str>, client_id: &ClientId) -> String {
+    nickname.map_or_else(|| short_id(client_id), str::to_string)
+}
+
+// "HH:MM" in UTC
+pub fn clock(timestamp: i64) -> String {
+    let seconds_of_day = timestamp.rem_euclid(86_400);
+    format!("{:02}:{:02}", seconds_of_day / 3600, seconds_of_day % 3600 / 60)
+}
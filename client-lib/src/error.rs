@@ -0,0 +1,41 @@
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("Failed to connect to {address}: {source}")]
+    Connect {
+        address: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Timed out connecting to {address} after {timeout:?}")]
+    ConnectTimeout {
+        address: String,
+        timeout: Duration,
+    },
+    #[error("Registration failed: {0}")]
+    Rejected(String),
+    #[error("The connection to the server is closed")]
+    Disconnected,
+    #[error("Timed out waiting for the server to accept the message")]
+    AckTimeout,
+    #[error("The server rejected the message: {0}")]
+    MessageRejected(String),
+    #[error("Timed out waiting for the server to answer the request")]
+    RequestTimeout,
+    // The server answered a request with a System message instead
+    #[error("Request refused: {0}")]
+    Refused(String),
+    #[error("Unexpected response from the server")]
+    UnexpectedResponse(Box<server::Message>),
+    #[cfg(feature = "tls")]
+    #[error("TLS setup failed: {0}")]
+    Tls(String),
+    #[error("Failed to encode message")]
+    Encode(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T, E = ClientError> = std::result::Result<T, E>;
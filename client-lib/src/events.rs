@@ -0,0 +1,96 @@
+use server::Message;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio_stream::Stream;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+
+#[derive(Debug, Clone)]
+pub enum ClientEvent {
+    // Any message from the server except Acks and Responses, which go to whoever is waiting for them.
+    // Nacks go to the waiter and are published too
+    Message(Message),
+    // This subscriber fell behind and missed `skipped` events
+    Lagged { skipped: u64 },
+    // Connecting or registering failed, followed by Reconnecting or Disconnected
+    ConnectFailed { reason: String },
+    // The connection was lost, attempt `attempt` to reconnect starts after `delay`
+    Reconnecting { attempt: u32, delay: Duration },
+    // Registered again after Reconnecting. The server announces the default room as on first connect,
+    // then the room the client was in once it is rejoined; the last status set is restored too
+    Reconnected,
+    // Always the last event of a stream
+    Disconnected,
+}
+
+// Sending half shared by the connection task and every handle
+#[derive(Clone)]
+pub(crate) struct EventBus {
+    sender: broadcast::Sender<ClientEvent>,
+    closed: Arc<AtomicBool>,
+}
+
+impl EventBus {
+    pub(crate) fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            sender,
+            closed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub(crate) fn publish(&self, event: ClientEvent) {
+        // No subscribers is fine, the event is simply dropped
+        let _ = self.sender.send(event);
+    }
+
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.publish(ClientEvent::Disconnected);
+    }
+
+    pub(crate) fn subscribe(&self) -> EventStream {
+        let receiver = self.sender.subscribe();
+        EventStream {
+            inner: BroadcastStream::new(receiver),
+            // Subscribing after the connection ended still yields Disconnected once
+            closed_before_subscribe: self.closed.load(Ordering::SeqCst),
+            finished: false,
+        }
+    }
+}
+
+// One independent subscription to a client's events, each subscriber sees every event
+pub struct EventStream {
+    inner: BroadcastStream<ClientEvent>,
+    closed_before_subscribe: bool,
+    finished: bool,
+}
+
+impl Stream for EventStream {
+    type Item = ClientEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ClientEvent>> {
+        if self.finished {
+            return Poll::Ready(None);
+        }
+        if self.closed_before_subscribe {
+            self.finished = true;
+            return Poll::Ready(Some(ClientEvent::Disconnected));
+        }
+
+        let event = match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+            Some(Ok(event)) => event,
+            Some(Err(BroadcastStreamRecvError::Lagged(skipped))) => ClientEvent::Lagged { skipped },
+            None => ClientEvent::Disconnected,
+        };
+        if matches!(event, ClientEvent::Disconnected) {
+            self.finished = true;
+        }
+        Poll::Ready(Some(event))
+    }
+}
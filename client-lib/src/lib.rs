@@ -1,124 +1,266 @@
-use anyhow::{Context, Result};
-use server::Message;
+mod builder;
+mod connection;
+mod delivery;
+mod display;
+mod error;
+mod events;
+mod presence;
+mod request;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "tls")]
+mod tls;
+mod typing;
+
+pub use builder::{
+    ClientBuilder, ReconnectPolicy, DEFAULT_CONNECT_TIMEOUT, DEFAULT_EVENT_CAPACITY, DEFAULT_OUTGOING_CAPACITY,
+};
+pub use delivery::{Delivery, PendingMessage, ACK_TIMEOUT, REQUEST_TIMEOUT};
+pub use display::{clock, display_name, short_id};
+pub use error::{ClientError, Result};
+pub use events::{ClientEvent, EventStream};
+pub use request::ClientRequest;
+#[cfg(feature = "tls")]
+pub use tls::TlsSettings;
+pub use typing::{TypingIndicators, TYPING_DEBOUNCE, TYPING_EXPIRY};
+
+use server::{
+    ClientId, ClientMsgId, Message, MessageId, RoomId, RoomSummary, Role, StoredMessage, UserInfo, UserStatus,
+};
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::TcpStream;
-use tokio::sync::{Mutex, mpsc};
+use tokio::sync::{Mutex, mpsc, oneshot};
 
-use uuid::Uuid;
+use crate::builder::{ClientRuntime, ClientSetup};
+use crate::connection::{Connection, SharedSession};
+use crate::delivery::{PendingAcks, PendingRequests};
+use crate::events::EventBus;
+use crate::presence::ActivityTracker;
 
+// Cheap to clone, every clone sends over the same connection
 #[derive(Clone)]
-pub struct Client {
-    pub message_sender: Arc<Mutex<mpsc::Sender<String>>>,
-    pub message_receiver: Arc<Mutex<mpsc::Receiver<Message>>>,
-    pub client_id: Uuid,
+pub struct ClientHandle {
+    outgoing: mpsc::Sender<ClientRequest>,
+    events: EventBus,
+    client_id: ClientId,
+    runtime: ClientRuntime,
+    activity: Arc<Mutex<ActivityTracker>>,
+    last_typing_sent: Arc<Mutex<Option<Instant>>>,
+    next_client_msg_id: Arc<AtomicU64>,
+    pending_acks: PendingAcks,
+    next_request_id: Arc<AtomicU64>,
+    pending_requests: PendingRequests,
+    session: SharedSession,
     _connection_handle: Arc<tokio::task::JoinHandle<()>>,
 }
 
-impl Client {
-    pub fn connect(address: &str) -> Result<Self> {
-        let rt = tokio::runtime::Handle::current();
-        let address = address.to_string();
+impl ClientHandle {
+    pub fn builder(address: impl Into<String>) -> ClientBuilder {
+        ClientBuilder::new(address)
+    }
 
-        let (outgoing_tx, outgoing_rx) = mpsc::channel::<String>(100);
-        let (incoming_tx, incoming_rx) = mpsc::channel::<Message>(100);
+    // Connects with the default settings, see `ClientBuilder::connect`
+    pub async fn connect(address: &str) -> Result<(Self, EventStream)> {
+        ClientBuilder::new(address).connect().await
+    }
 
-        let client_id = Uuid::new_v4();
-        let client_id_clone = client_id.clone();
+    // Starts the connection task, which makes the first connection itself unless given one
+    pub(crate) fn start(setup: ClientSetup, initial: Option<Connection>) -> Self {
+        let ClientSetup { runtime, config, events, session, outgoing_capacity } = setup;
+        let client_id = config.client_id;
+        let (outgoing_tx, outgoing_rx) = mpsc::channel::<ClientRequest>(outgoing_capacity);
+        let pending_acks: PendingAcks = Arc::new(Mutex::new(HashMap::new()));
+        let pending_requests: PendingRequests = Arc::new(Mutex::new(HashMap::new()));
 
-        let connection_handle = rt.spawn(async move {
-            if let Err(e) =
-                Self::run_connection(&address, outgoing_rx, incoming_tx, client_id_clone).await
-            {
-                eprintln!("Connection error: {}", e);
-            }
-        });
+        let connection_handle = runtime.handle.spawn(connection::run(
+            config,
+            initial,
+            outgoing_rx,
+            events.clone(),
+            pending_acks.clone(),
+            pending_requests.clone(),
+            session.clone(),
+        ));
 
-        Ok(Client {
+        ClientHandle {
             client_id,
-            message_sender: Arc::new(Mutex::new(outgoing_tx)),
-            message_receiver: Arc::new(Mutex::new(incoming_rx)),
+            outgoing: outgoing_tx,
+            events,
+            runtime,
+            activity: Arc::new(Mutex::new(ActivityTracker::new())),
+            last_typing_sent: Arc::new(Mutex::new(None)),
+            next_client_msg_id: Arc::new(AtomicU64::new(1)),
+            pending_acks,
+            next_request_id: Arc::new(AtomicU64::new(1)),
+            pending_requests,
+            session,
             _connection_handle: Arc::new(connection_handle),
-        })
+        }
     }
 
-    pub async fn send_message(&self, message: &str) -> Result<()> {
-        self.message_sender
-            .lock()
-            .await
-            .send(message.into())
-            .await
-            .with_context(|| "Failed to send message")
+    pub fn client_id(&self) -> ClientId {
+        self.client_id
     }
 
-    async fn run_connection(
-        address: &str,
-        outgoing_rx: mpsc::Receiver<String>,
-        incoming_tx: mpsc::Sender<Message>,
-        client_id: Uuid,
-    ) -> Result<()> {
-        let stream = TcpStream::connect(address)
-            .await
-            .with_context(|| format!("Failed to connect to {}", address))?;
+    // Proves this client owns its id; keep it and pass it to `ClientBuilder::token` to connect as the same id
+    // later. The server issues it on the id's first connection, before that it is the one given to the builder
+    pub async fn token(&self) -> Option<String> {
+        self.session.lock().await.token.clone()
+    }
 
-        let (read_stream, write_stream) = tokio::io::split(stream);
+    // The room the server last placed this client in
+    pub async fn current_room(&self) -> RoomId {
+        self.session.lock().await.room.clone()
+    }
 
+    // A new stream of the events received from now on, independent of other subscribers
+    pub fn subscribe(&self) -> EventStream {
+        self.events.subscribe()
+    }
+
+    // Returns once the message is queued; await `PendingMessage::accepted` for the server's Ack
+    pub async fn send_message(&self, message: &str) -> Result<PendingMessage> {
+        self.send_chat(message, self.next_client_msg_id(), None).await
+    }
+
+    pub async fn reply(&self, parent: MessageId, message: &str) -> Result<PendingMessage> {
+        self.send_chat(message, self.next_client_msg_id(), Some(parent)).await
+    }
+
+    // Lets callers record the id of an optimistic local copy before the message is queued
+    pub fn next_client_msg_id(&self) -> ClientMsgId {
+        self.next_client_msg_id.fetch_add(1, Ordering::Relaxed)
+    }
 
-        // Spawn task to handle outgoing messages
-        let outgoing_task = Self::spawn_outgoing_handler(write_stream, outgoing_rx, client_id);
+    pub async fn send_chat(
+        &self,
+        message: &str,
+        client_msg_id: ClientMsgId,
+        reply_to: Option<MessageId>,
+    ) -> Result<PendingMessage> {
+        self.record_activity().await?;
+        *self.last_typing_sent.lock().await = None;
 
-        // Spawn task to handle incoming messages
-        let incoming_task = Self::spawn_incoming_handler(read_stream, incoming_tx);
+        let (ack_tx, ack_rx) = oneshot::channel();
+        self.pending_acks.lock().await.insert(client_msg_id, ack_tx);
 
-        // Wait for either task to complete
-        tokio::select! {
-            _ = incoming_task => {},
-            _ = outgoing_task => {},
+        let request = ClientRequest::Chat {
+            content: message.to_string(),
+            client_msg_id: Some(client_msg_id),
+            reply_to,
+        };
+        if let Err(e) = self.send(request).await {
+            self.pending_acks.lock().await.remove(&client_msg_id);
+            return Err(e);
         }
+        Ok(PendingMessage::new(client_msg_id, ack_rx, self.pending_acks.clone()))
+    }
 
-        Ok(())
+    // Slash commands are answered with System messages instead of an Ack
+    pub async fn send_command(&self, line: &str) -> Result<()> {
+        self.record_activity().await?;
+        self.send(ClientRequest::Chat { content: line.to_string(), client_msg_id: None, reply_to: None }).await
     }
 
-    fn spawn_incoming_handler(
-        read_stream: tokio::io::ReadHalf<TcpStream>,
-        incoming_tx: mpsc::Sender<Message>
-    ) -> tokio::task::JoinHandle<()> {
-        tokio::spawn(async move {
-            let mut reader = BufReader::new(read_stream);
-            let mut line_buffer = String::new();
+    pub async fn edit_message(&self, message_id: MessageId, new_content: &str) -> Result<()> {
+        self.send(ClientRequest::Edit { message_id, new_content: new_content.to_string() }).await
+    }
 
-            loop {
-                line_buffer.clear();
-                match reader.read_line(&mut line_buffer).await {
-                    Ok(0) => break, // Connection closed
-                    Ok(_) => {
-                        let message_text = line_buffer.trim();
-                        if let Ok(message) = serde_json::from_str::<Message>(&message_text) {
-                            incoming_tx.send(message).await.ok();
-                        }
-                    }
-                    Err(_) => break, // Connection error
-                }
-            }
-        })
+    pub async fn delete_message(&self, message_id: MessageId) -> Result<()> {
+        self.send(ClientRequest::Delete { message_id }).await
+    }
+
+    pub async fn react(&self, message_id: MessageId, emoji: &str) -> Result<()> {
+        self.send(ClientRequest::React { message_id, emoji: emoji.to_string() }).await
+    }
+
+    pub async fn unreact(&self, message_id: MessageId, emoji: &str) -> Result<()> {
+        self.send(ClientRequest::Unreact { message_id, emoji: emoji.to_string() }).await
+    }
+
+    // `room: None` grants the server-wide role
+    pub async fn grant_role(&self, client_id: ClientId, role: Role, room: Option<&str>) -> Result<()> {
+        let room = room.map(str::to_string);
+        self.send(ClientRequest::GrantRole { client_id, role, room }).await
+    }
+
+    pub async fn revoke_role(&self, client_id: ClientId, room: Option<&str>) -> Result<()> {
+        let room = room.map(str::to_string);
+        self.send(ClientRequest::RevokeRole { client_id, room }).await
+    }
+
+    // The server answers with a Message::Thread holding the root and all replies below it
+    pub async fn request_thread(&self, message_id: MessageId) -> Result<()> {
+        self.send(ClientRequest::Thread { message_id }).await
+    }
+
+    pub async fn mark_read(&self, message_id: MessageId) -> Result<()> {
+        self.send(ClientRequest::MarkRead { message_id }).await
     }
 
-    fn spawn_outgoing_handler(
-        mut write_stream: tokio::io::WriteHalf<TcpStream>,
-        mut outgoing_rx: mpsc::Receiver<String>,
-        client_id: Uuid,
-    ) -> tokio::task::JoinHandle<()> {
-        tokio::spawn(async move {
-            // Send authenticating message
-            if Self::send_message_to_server(&mut write_stream, Message::log(client_id)).await.is_err() {
-                return;
+    // Call on every edit of the input; notifications are debounced to one per TYPING_DEBOUNCE
+    pub async fn notify_typing(&self) -> Result<()> {
+        self.record_activity().await?;
+        {
+            let mut last_sent = self.last_typing_sent.lock().await;
+            if last_sent.is_some_and(|sent| sent.elapsed() < TYPING_DEBOUNCE) {
+                return Ok(());
             }
+            *last_sent = Some(Instant::now());
+        }
+        let room = self.current_room().await;
+        self.send(ClientRequest::Typing { room }).await
+    }
+
+    pub async fn set_status(&self, status: UserStatus, status_text: Option<&str>) -> Result<()> {
+        self.activity.lock().await.set_status(status, status_text);
+        let status_text = status_text.map(str::to_string);
+        self.send(ClientRequest::SetStatus { status, status_text }).await
+    }
+
+    // Lifts an automatic away status; call this on user input that is not a sent message
+    pub async fn record_activity(&self) -> Result<()> {
+        let restore = {
+            let mut activity = self.activity.lock().await;
+            activity.touch().then(|| ClientRequest::SetStatus {
+                status: UserStatus::Online,
+                status_text: activity.status_text().map(str::to_string),
+            })
+        };
+        if let Some(request) = restore {
+            self.send(request).await?;
+        }
+        Ok(())
+    }
 
-            while let Some(message) = outgoing_rx.recv().await {
-                if Self::send_message_to_server(&mut write_stream, Message::chat(client_id, message.as_str()))
-                    .await
-                    .is_err()
+    // Marks the user away after `idle_after` without activity, until the next activity.
+    // The task only holds weak references, so it ends once every handle is dropped; the caller owns
+    // the returned JoinHandle and can abort it to stop sooner
+    pub fn enable_auto_away(&self, idle_after: Duration) -> tokio::task::JoinHandle<()> {
+        let outgoing = self.outgoing.downgrade();
+        let activity = Arc::downgrade(&self.activity);
+        let check_every = (idle_after / 4).max(Duration::from_secs(1));
+
+        self.runtime.handle.spawn(async move {
+            loop {
+                tokio::time::sleep(check_every).await;
+                let (Some(outgoing), Some(activity)) = (outgoing.upgrade(), activity.upgrade()) else {
+                    break;
+                };
+                let away = {
+                    let mut activity = activity.lock().await;
+                    activity
+                        .check_idle(idle_after)
+                        .then(|| ClientRequest::SetStatus {
+                            status: UserStatus::Away,
+                            status_text: activity.status_text().map(str::to_string),
+                        })
+                };
+                if let Some(request) = away
+                    && outgoing.send(request).await.is_err()
                 {
                     break;
                 }
@@ -126,15 +268,70 @@ impl Client {
         })
     }
 
-    async fn send_message_to_server(
-        writer: &mut tokio::io::WriteHalf<TcpStream>,
-        message: Message,
-    ) -> Result<()> {
-        let json = serde_json::to_string(&message)?;
-        let message_with_newline = format!("{}\n", json);
-        writer.write_all(message_with_newline.as_bytes()).await?;
-        writer.flush().await?;
-        println!("Message sent: {}", message_with_newline);
-        Ok(())
+    pub async fn request_who(&self) -> Result<()> {
+        self.send(ClientRequest::Who).await
+    }
+
+    // Sends a query and waits for the server's answer to it, see REQUEST_TIMEOUT.
+    // Unsolicited messages keep going to the event streams meanwhile
+    pub async fn request(&self, query: ClientRequest) -> Result<Message> {
+        let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let (response_tx, response_rx) = oneshot::channel();
+        self.pending_requests.lock().await.insert(request_id, response_tx);
+
+        let message = Message::request(request_id, query.into_message(self.client_id));
+        if let Err(e) = self.send(ClientRequest::Raw(message)).await {
+            self.pending_requests.lock().await.remove(&request_id);
+            return Err(e);
+        }
+
+        match tokio::time::timeout(REQUEST_TIMEOUT, response_rx).await {
+            Ok(Ok(Message::System { content })) => Err(ClientError::Refused(content)),
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(_)) => Err(ClientError::Disconnected),
+            Err(_) => {
+                self.pending_requests.lock().await.remove(&request_id);
+                Err(ClientError::RequestTimeout)
+            }
+        }
+    }
+
+    pub async fn who(&self) -> Result<Vec<UserInfo>> {
+        match self.request(ClientRequest::Who).await? {
+            Message::WhoList { clients } => Ok(clients),
+            other => Err(ClientError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    // Up to `limit` messages of the current room before `before`, or the latest ones, oldest first
+    pub async fn history(
+        &self,
+        before: Option<MessageId>,
+        limit: Option<usize>,
+    ) -> Result<(RoomId, Vec<StoredMessage>)> {
+        match self.request(ClientRequest::History { before, limit }).await? {
+            Message::History { room, messages } => Ok((room, messages)),
+            other => Err(ClientError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    pub async fn rooms(&self) -> Result<Vec<RoomSummary>> {
+        match self.request(ClientRequest::Rooms).await? {
+            Message::RoomList { rooms } => Ok(rooms),
+            other => Err(ClientError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    // The id of the thread's root and its messages, the root first
+    pub async fn thread(&self, message_id: MessageId) -> Result<(MessageId, Vec<StoredMessage>)> {
+        match self.request(ClientRequest::Thread { message_id }).await? {
+            Message::Thread { root, messages } => Ok((root, messages)),
+            other => Err(ClientError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    // Queues any request as is; the helpers above also track activity, typing and Acks
+    pub async fn send(&self, request: ClientRequest) -> Result<()> {
+        self.outgoing.send(request).await.map_err(|_| ClientError::Disconnected)
     }
 }
@@ -0,0 +1,52 @@
+use std::time::{Duration, Instant};
+
+use server::UserStatus;
+
+pub(crate) struct ActivityTracker {
+    last_active: Instant,
+    status: UserStatus,
+    status_text: Option<String>,
+    auto_away: bool,
+}
+
+impl ActivityTracker {
+    pub(crate) fn new() -> Self {
+        Self {
+            last_active: Instant::now(),
+            status: UserStatus::Online,
+            status_text: None,
+            auto_away: false,
+        }
+    }
+
+    pub(crate) fn status_text(&self) -> Option<&str> {
+        self.status_text.as_deref()
+    }
+
+    pub(crate) fn set_status(&mut self, status: UserStatus, status_text: Option<&str>) {
+        self.status = status;
+        self.status_text = status_text.map(str::to_string);
+        self.auto_away = false;
+    }
+
+    // Returns true when the user was marked away automatically and is now back
+    pub(crate) fn touch(&mut self) -> bool {
+        self.last_active = Instant::now();
+        if self.auto_away {
+            self.status = UserStatus::Online;
+            self.auto_away = false;
+            return true;
+        }
+        false
+    }
+
+    // Only an explicit "online" status is switched to away, so busy or invisible users stay as they are
+    pub(crate) fn check_idle(&mut self, idle_after: Duration) -> bool {
+        if self.status == UserStatus::Online && self.last_active.elapsed() >= idle_after {
+            self.status = UserStatus::Away;
+            self.auto_away = true;
+            return true;
+        }
+        false
+    }
+}
@@ -0,0 +1,90 @@
+use server::{ClientId, ClientMsgId, Message, MessageId, Role, RoomId, UserStatus};
+
+// Everything a client can ask of the server. The connection turns a request into
+// its protocol message when writing it, filling in the client's id where needed
+#[derive(Debug, Clone)]
+pub enum ClientRequest {
+    // With a `client_msg_id` the server answers with an Ack, see `ClientHandle::send_chat`.
+    // Content starting with '/' runs a slash command instead
+    Chat {
+        content: String,
+        client_msg_id: Option<ClientMsgId>,
+        reply_to: Option<MessageId>,
+    },
+    Edit {
+        message_id: MessageId,
+        new_content: String,
+    },
+    Delete {
+        message_id: MessageId,
+    },
+    React {
+        message_id: MessageId,
+        emoji: String,
+    },
+    Unreact {
+        message_id: MessageId,
+        emoji: String,
+    },
+    // Answered with a Message::Thread holding the root and all replies below it
+    Thread {
+        message_id: MessageId,
+    },
+    MarkRead {
+        message_id: MessageId,
+    },
+    Typing {
+        room: RoomId,
+    },
+    SetStatus {
+        status: UserStatus,
+        status_text: Option<String>,
+    },
+    // Answered with a Message::WhoList
+    Who,
+    // Answered with a Message::History of the current room, see `ClientHandle::history`
+    History {
+        before: Option<MessageId>,
+        limit: Option<usize>,
+    },
+    // Answered with a Message::RoomList
+    Rooms,
+    // `room: None` grants the server-wide role
+    GrantRole {
+        client_id: ClientId,
+        role: Role,
+        room: Option<RoomId>,
+    },
+    RevokeRole {
+        client_id: ClientId,
+        room: Option<RoomId>,
+    },
+    Heartbeat,
+    // Any protocol message, sent unchanged
+    Raw(Message),
+}
+
+impl ClientRequest {
+    pub(crate) fn into_message(self, own_id: ClientId) -> Message {
+        match self {
+            ClientRequest::Chat { content, client_msg_id, reply_to } => {
+                Message::outgoing_chat(own_id, &content, client_msg_id, reply_to)
+            }
+            ClientRequest::Edit { message_id, new_content } => Message::edit(message_id, &new_content),
+            ClientRequest::Delete { message_id } => Message::delete(message_id),
+            ClientRequest::React { message_id, emoji } => Message::react(message_id, &emoji),
+            ClientRequest::Unreact { message_id, emoji } => Message::unreact(message_id, &emoji),
+            ClientRequest::Thread { message_id } => Message::thread_request(message_id),
+            ClientRequest::MarkRead { message_id } => Message::read_receipt(message_id, own_id),
+            ClientRequest::Typing { room } => Message::typing(own_id, &room),
+            ClientRequest::SetStatus { status, status_text } => Message::set_status(status, status_text.as_deref()),
+            ClientRequest::Who => Message::who_request(),
+            ClientRequest::History { before, limit } => Message::history_request(before, limit),
+            ClientRequest::Rooms => Message::room_list_request(),
+            ClientRequest::GrantRole { client_id, role, room } => Message::grant_role(client_id, role, room.as_deref()),
+            ClientRequest::RevokeRole { client_id, room } => Message::revoke_role(client_id, room.as_deref()),
+            ClientRequest::Heartbeat => Message::heartbeat(),
+            ClientRequest::Raw(message) => message,
+        }
+    }
+}
@@ -0,0 +1,199 @@
+// Helpers for tests that need a real server: an in-process ChatInstance on an ephemeral
+// port and clients whose expectations panic with what arrived instead after a timeout
+use server::chat::ChatInstance;
+use server::{ClientId, Message};
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio_stream::StreamExt;
+
+use crate::{ClientBuilder, ClientEvent, ClientHandle, EventStream};
+
+// How long an expectation waits before failing the test
+pub const EXPECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Stops accepting and closes every connection when dropped
+pub struct TestServer {
+    address: String,
+    chat: Arc<ChatInstance>,
+    accept_task: JoinHandle<()>,
+}
+
+impl TestServer {
+    // A server without a data directory, so nothing is read from or written to disk
+    pub async fn start() -> Self {
+        Self::with_chat(ChatInstance::new()).await
+    }
+
+    // For a server configured up front, e.g. with a MOTD, filters or roles
+    pub async fn with_chat(chat: ChatInstance) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("failed to bind a test port");
+        let address = listener.local_addr().expect("test listener has no address").to_string();
+        let chat = Arc::new(chat);
+
+        let accepting = Arc::clone(&chat);
+        let accept_task = tokio::spawn(async move {
+            // Connections live in the set, so aborting this task closes them too
+            let mut connections = JoinSet::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                let chat = Arc::clone(&accepting);
+                connections.spawn(async move {
+                    let _ = chat.handle_connection(stream).await;
+                });
+            }
+        });
+
+        Self { address, chat, accept_task }
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn chat(&self) -> &ChatInstance {
+        &self.chat
+    }
+
+    // A builder pointed at this server, for clients with other options
+    pub fn builder(&self) -> ClientBuilder {
+        ClientHandle::builder(self.address.clone())
+    }
+
+    pub async fn connect(&self, nickname: &str) -> TestClient {
+        self.connect_with(self.builder().nickname(nickname)).await
+    }
+
+    pub async fn connect_with(&self, builder: ClientBuilder) -> TestClient {
+        let (handle, events) = builder.connect().await.expect("test client failed to connect");
+        TestClient { handle, events }
+    }
+
+    // Clients named client1, client2, ... each connected after the previous one registered
+    pub async fn connect_many(&self, count: usize) -> Vec<TestClient> {
+        let mut clients = Vec::with_capacity(count);
+        for n in 1..=count {
+            clients.push(self.connect(&format!("client{}", n)).await);
+        }
+        clients
+    }
+
+    // Closes every connection at once, as if the server went away
+    pub fn shutdown(&self) {
+        self.accept_task.abort();
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+    }
+}
+
+// A connected client that derefs to its ClientHandle for sending
+pub struct TestClient {
+    handle: ClientHandle,
+    events: EventStream,
+}
+
+impl Deref for TestClient {
+    type Target = ClientHandle;
+
+    fn deref(&self) -> &ClientHandle {
+        &self.handle
+    }
+}
+
+impl TestClient {
+    pub fn handle(&self) -> &ClientHandle {
+        &self.handle
+    }
+
+    // The next event, or None if nothing arrives within `timeout`
+    pub async fn next_event(&mut self, timeout: Duration) -> Option<ClientEvent> {
+        tokio::time::timeout(timeout, self.events.next()).await.ok().flatten()
+    }
+
+    // Skips events until a message matches `predicate`
+    pub async fn expect_message(&mut self, description: &str, predicate: impl Fn(&Message) -> bool) -> Message {
+        let mut skipped = Vec::new();
+        let found = tokio::time::timeout(EXPECT_TIMEOUT, async {
+            while let Some(event) = self.events.next().await {
+                match event {
+                    ClientEvent::Message(message) if predicate(&message) => return Some(message),
+                    event => skipped.push(event),
+                }
+            }
+            None
+        })
+        .await;
+        match found {
+            Ok(Some(message)) => message,
+            Ok(None) => panic!("stream ended while waiting for {}, got {:?}", description, skipped),
+            Err(_) => panic!("timed out waiting for {}, got {:?}", description, skipped),
+        }
+    }
+
+    // The next chat message with exactly this content, from anyone
+    pub async fn expect_chat(&mut self, content: &str) -> Message {
+        self.expect_message(&format!("chat {:?}", content), |message| {
+            matches!(message, Message::Chat { content: got, .. } if got == content)
+        })
+        .await
+    }
+
+    // The content of the next chat message from `from`
+    pub async fn expect_chat_from(&mut self, from: ClientId) -> String {
+        let message = self
+            .expect_message(&format!("chat from {}", from), |message| {
+                matches!(message, Message::Chat { client_id, .. } if *client_id == from)
+            })
+            .await;
+        match message {
+            Message::Chat { content, .. } => content,
+            _ => unreachable!(),
+        }
+    }
+
+    // The next System message containing `text`
+    pub async fn expect_system(&mut self, text: &str) -> String {
+        let message = self
+            .expect_message(&format!("system message containing {:?}", text), |message| {
+                matches!(message, Message::System { content } if content.contains(text))
+            })
+            .await;
+        match message {
+            Message::System { content } => content,
+            _ => unreachable!(),
+        }
+    }
+
+    // Skips everything until the connection is reported closed
+    pub async fn expect_disconnected(&mut self) {
+        let mut skipped = Vec::new();
+        let closed = tokio::time::timeout(EXPECT_TIMEOUT, async {
+            while let Some(event) = self.events.next().await {
+                if matches!(event, ClientEvent::Disconnected) {
+                    return true;
+                }
+                skipped.push(event);
+            }
+            true
+        })
+        .await;
+        assert!(closed.is_ok(), "timed out waiting for the disconnect, got {:?}", skipped);
+    }
+
+    // Fails if a message matching `predicate` arrives within `within`
+    pub async fn expect_no_message(&mut self, within: Duration, predicate: impl Fn(&Message) -> bool) {
+        let _ = tokio::time::timeout(within, async {
+            while let Some(event) = self.events.next().await {
+                if let ClientEvent::Message(message) = event {
+                    assert!(!predicate(&message), "unexpected message {:?}", message);
+                }
+            }
+        })
+        .await;
+    }
+}
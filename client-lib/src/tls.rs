@@ -0,0 +1,63 @@
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::rustls::{self, RootCertStore};
+
+use crate::connection::BoxedTransport;
+use crate::error::{ClientError, Result};
+
+// Server certificates are checked against the bundled web PKI roots plus any added here
+#[derive(Debug, Clone, Default)]
+pub struct TlsSettings {
+    server_name: Option<String>,
+    root_certificates: Vec<CertificateDer<'static>>,
+}
+
+impl TlsSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Name to verify the certificate against, defaults to the host part of the address
+    pub fn server_name(mut self, name: impl Into<String>) -> Self {
+        self.server_name = Some(name.into());
+        self
+    }
+
+    // A DER encoded CA certificate to trust, e.g. for a self-signed server certificate
+    pub fn add_root_certificate(mut self, der: impl Into<Vec<u8>>) -> Self {
+        self.root_certificates.push(CertificateDer::from(der.into()));
+        self
+    }
+}
+
+pub(crate) async fn wrap(stream: TcpStream, address: &str, settings: &TlsSettings) -> Result<BoxedTransport> {
+    let mut roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    for certificate in &settings.root_certificates {
+        roots.add(certificate.clone()).map_err(|e| ClientError::Tls(e.to_string()))?;
+    }
+
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| ClientError::Tls(e.to_string()))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let host = match &settings.server_name {
+        Some(name) => name.clone(),
+        None => host_of(address).to_string(),
+    };
+    let server_name = ServerName::try_from(host).map_err(|e| ClientError::Tls(e.to_string()))?;
+
+    let stream = TlsConnector::from(Arc::new(config)).connect(server_name, stream).await?;
+    Ok(Box::new(stream))
+}
+
+// "example.com:8080" -> "example.com", "[::1]:8080" -> "::1"
+fn host_of(address: &str) -> &str {
+    let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+    host.trim_start_matches('[').trim_end_matches(']')
+}
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use server::ClientId;
+
+// Minimum gap between two typing notifications sent by this client
+pub const TYPING_DEBOUNCE: Duration = Duration::from_secs(3);
+// How long a received typing notification stays visible without a refresh
+pub const TYPING_EXPIRY: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+pub struct TypingIndicators {
+    typers: HashMap<ClientId, Instant>,
+}
+
+impl TypingIndicators {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, client_id: ClientId) {
+        self.typers.insert(client_id, Instant::now());
+    }
+
+    pub fn clear(&mut self, client_id: &ClientId) {
+        self.typers.remove(client_id);
+    }
+
+    // Drops expired entries, returns true if anything changed
+    pub fn prune(&mut self) -> bool {
+        let before = self.typers.len();
+        self.typers.retain(|_, since| since.elapsed() < TYPING_EXPIRY);
+        before != self.typers.len()
+    }
+
+    pub fn active(&self) -> Vec<ClientId> {
+        let mut typers: Vec<_> = self
+            .typers
+            .iter()
+            .filter(|(_, since)| since.elapsed() < TYPING_EXPIRY)
+            .map(|(client_id, _)| *client_id)
+            .collect();
+        typers.sort();
+        typers
+    }
+}
@@ -0,0 +1,82 @@
+use client_lib::testing::TestServer;
+use server::Message;
+use std::time::Duration;
+
+#[tokio::test]
+async fn chat_reaches_everyone_else_in_the_room() {
+    let server = TestServer::start().await;
+    let mut clients = server.connect_many(3).await;
+
+    let sender = clients[0].client_id();
+    let delivery = clients[0].send_message("hello all").await.unwrap().accepted().await.unwrap();
+    for client in &mut clients[1..] {
+        let message = client.expect_chat("hello all").await;
+        let Message::Chat { client_id, id, room, .. } = message else { unreachable!() };
+        assert_eq!(client_id, sender);
+        assert_eq!(id, Some(delivery.server_msg_id));
+        assert_eq!(room, "general");
+    }
+
+    // Without echo the sender only gets the ack
+    clients[0]
+        .expect_no_message(Duration::from_millis(200), |message| matches!(message, Message::Chat { .. }))
+        .await;
+}
+
+#[tokio::test]
+async fn echo_delivers_the_senders_own_messages() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect_with(server.builder().nickname("alice").echo(true)).await;
+
+    alice.send_message("hi me").await.unwrap();
+    assert_eq!(alice.expect_chat_from(alice.client_id()).await, "hi me");
+}
+
+#[tokio::test]
+async fn rooms_are_isolated() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect("alice").await;
+    let mut bob = server.connect("bob").await;
+    let mut carol = server.connect("carol").await;
+
+    bob.send_command("/join ops").await.unwrap();
+    bob.expect_message("the move to #ops", |message| matches!(message, Message::RoomChanged { room, .. } if room == "ops"))
+        .await;
+    carol.send_command("/join ops").await.unwrap();
+    carol.expect_message("the move to #ops", |message| matches!(message, Message::RoomChanged { room, .. } if room == "ops"))
+        .await;
+    assert_eq!(carol.current_room().await, "ops");
+    assert_eq!(alice.current_room().await, "general");
+
+    // Typing indicators follow the room too
+    carol.notify_typing().await.unwrap();
+    bob.expect_message("carol typing", |message| matches!(message, Message::Typing { room, .. } if room == "ops"))
+        .await;
+    alice
+        .expect_no_message(Duration::from_millis(200), |message| matches!(message, Message::Typing { .. }))
+        .await;
+
+    bob.send_message("ops only").await.unwrap().accepted().await.unwrap();
+    carol.expect_chat("ops only").await;
+    alice
+        .expect_no_message(Duration::from_millis(200), |message| matches!(message, Message::Chat { .. }))
+        .await;
+
+    alice.send_message("general only").await.unwrap().accepted().await.unwrap();
+    bob.expect_no_message(Duration::from_millis(200), |message| matches!(message, Message::Chat { .. }))
+        .await;
+}
+
+#[tokio::test]
+async fn messages_arrive_in_the_order_they_were_sent() {
+    let server = TestServer::start().await;
+    let alice = server.connect("alice").await;
+    let mut bob = server.connect("bob").await;
+
+    for n in 0..20 {
+        alice.send_message(&format!("message {}", n)).await.unwrap();
+    }
+    for n in 0..20 {
+        assert_eq!(bob.expect_chat_from(alice.client_id()).await, format!("message {}", n));
+    }
+}
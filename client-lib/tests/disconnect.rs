@@ -0,0 +1,138 @@
+use client_lib::testing::TestServer;
+use client_lib::{ClientError, ClientEvent, ClientHandle, ReconnectPolicy};
+use server::{Message, PresenceEvent, Role, UserStatus};
+use std::time::Duration;
+use tokio_stream::StreamExt;
+
+#[tokio::test]
+async fn dropped_clients_leave_with_a_presence_notice() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect("alice").await;
+    let bob = server.connect("bob").await;
+    let bob_id = bob.client_id();
+
+    drop(bob);
+    let left = alice
+        .expect_message("bob leaving", |message| {
+            matches!(message, Message::Presence { event: PresenceEvent::Left, .. })
+        })
+        .await;
+    assert!(matches!(left, Message::Presence { client_id, nickname, .. } if client_id == bob_id && nickname == "bob"));
+    assert!(server.chat().nickname(&bob_id).await.is_none());
+}
+
+#[tokio::test]
+async fn auto_away_does_not_keep_a_dropped_client_connected() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect("alice").await;
+    let bob = server.connect("bob").await;
+    let auto_away = bob.enable_auto_away(Duration::from_secs(4));
+
+    drop(bob);
+    alice
+        .expect_message("bob leaving", |message| {
+            matches!(message, Message::Presence { event: PresenceEvent::Left, .. })
+        })
+        .await;
+    tokio::time::timeout(Duration::from_secs(5), auto_away).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn nicknames_are_free_again_after_a_disconnect() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect("alice").await;
+    drop(server.connect("bob").await);
+    alice
+        .expect_message("bob leaving", |message| {
+            matches!(message, Message::Presence { event: PresenceEvent::Left, .. })
+        })
+        .await;
+
+    let bob = server.connect("bob").await;
+    assert_eq!(server.chat().nickname(&bob.client_id()).await.as_deref(), Some("bob"));
+}
+
+#[tokio::test]
+async fn server_shutdown_disconnects_clients() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect("alice").await;
+    let mut bob = server.connect("bob").await;
+
+    server.shutdown();
+    alice.expect_disconnected().await;
+    bob.expect_disconnected().await;
+    assert!(matches!(alice.send_message("anyone?").await, Err(ClientError::Disconnected)));
+    assert!(bob.next_event(Duration::from_millis(100)).await.is_none());
+}
+
+#[tokio::test]
+async fn failed_background_connects_are_reported_on_the_event_stream() {
+    // Nothing listens on a port that was just freed
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    drop(listener);
+
+    let (client, mut events) = ClientHandle::builder(address).connect_in_background().unwrap();
+    let failed = tokio::time::timeout(Duration::from_secs(5), events.next()).await.unwrap();
+    assert!(matches!(failed, Some(ClientEvent::ConnectFailed { reason }) if reason.starts_with("Failed to connect")));
+    let closed = tokio::time::timeout(Duration::from_secs(5), events.next()).await.unwrap();
+    assert!(matches!(closed, Some(ClientEvent::Disconnected)));
+    assert!(matches!(client.send_message("anyone?").await, Err(ClientError::Disconnected)));
+}
+
+#[tokio::test]
+async fn reconnected_clients_get_their_room_and_status_back() {
+    let server = TestServer::start().await;
+    let owner = server.connect("owner").await;
+    server.chat().assign_role(owner.client_id(), None, Role::Owner).await.unwrap();
+    let backoff = ReconnectPolicy::backoff(Duration::from_millis(20), Duration::from_millis(100));
+    let mut alice = server.connect_with(server.builder().nickname("alice").reconnect(backoff)).await;
+    let alice_id = alice.client_id();
+
+    alice.send_command("/join ops").await.unwrap();
+    alice.expect_message("joining #ops", |message| matches!(message, Message::RoomChanged { room, .. } if room == "ops")).await;
+    alice.set_status(UserStatus::Away, Some("lunch")).await.unwrap();
+
+    owner.send_command("/disconnect alice").await.unwrap();
+    let reconnected = tokio::time::timeout(Duration::from_secs(5), async {
+        while let Some(event) = alice.next_event(Duration::from_secs(5)).await {
+            if matches!(event, ClientEvent::Reconnected) {
+                return true;
+            }
+        }
+        false
+    })
+    .await;
+    assert_eq!(reconnected, Ok(true));
+    alice.expect_message("rejoining #ops", |message| matches!(message, Message::RoomChanged { room, .. } if room == "ops")).await;
+    assert_eq!(alice.current_room().await, "ops");
+
+    // The kicked session ending late must not take the new connection with it
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(server.chat().current_room(&alice_id).await.as_deref(), Some("ops"));
+    let online = server.chat().online_clients().await;
+    let restored = online.iter().find(|member| member.client_id == alice_id).unwrap();
+    assert_eq!((restored.status, restored.status_text.as_deref()), (UserStatus::Away, Some("lunch")));
+}
+
+#[tokio::test]
+async fn messages_queued_while_reconnecting_are_delivered_afterwards() {
+    let server = TestServer::start().await;
+    let owner = server.connect("owner").await;
+    server.chat().assign_role(owner.client_id(), None, Role::Owner).await.unwrap();
+    let backoff = ReconnectPolicy::backoff(Duration::from_millis(300), Duration::from_millis(300));
+    let mut alice = server.connect_with(server.builder().nickname("alice").reconnect(backoff)).await;
+
+    owner.send_command("/disconnect alice").await.unwrap();
+    loop {
+        match alice.next_event(Duration::from_secs(5)).await {
+            Some(ClientEvent::Reconnecting { .. }) => break,
+            Some(_) => {}
+            None => panic!("alice did not start reconnecting"),
+        }
+    }
+    let pending = alice.send_message("sent while away").await.unwrap();
+    let delivery = pending.accepted().await.unwrap();
+    let (_, stored) = alice.history(None, None).await.unwrap();
+    assert!(stored.iter().any(|message| message.id == delivery.server_msg_id && message.content == "sent while away"));
+}
@@ -0,0 +1,56 @@
+use client_lib::testing::TestServer;
+use server::chat::ChatInstance;
+use server::{Message, Role};
+use std::time::Duration;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn muted_authors_cannot_rewrite_their_messages() {
+    let server = TestServer::start().await;
+    let owner = server.connect("owner").await;
+    server.chat().assign_role(owner.client_id(), None, Role::Owner).await.unwrap();
+    let mut alice = server.connect("alice").await;
+    let mut bob = server.connect("bob").await;
+
+    let sent = alice.send_message("first draft").await.unwrap().accepted().await.unwrap();
+    bob.expect_chat("first draft").await;
+
+    owner.send_command("/mute alice 10m").await.unwrap();
+    alice.expect_system("muted you in #general").await;
+
+    alice.edit_message(sent.server_msg_id, "second draft").await.unwrap();
+    alice.expect_system("You are muted in #general").await;
+    bob.expect_no_message(Duration::from_millis(200), |message| matches!(message, Message::Edit { .. }))
+        .await;
+}
+
+#[tokio::test]
+async fn history_and_reactions_survive_a_restart() {
+    let data_dir = std::env::temp_dir().join(format!("chat-history-{}", Uuid::new_v4().simple()));
+    let server = TestServer::with_chat(ChatInstance::with_data_dir(&data_dir).unwrap()).await;
+    let mut alice = server.connect("alice").await;
+    let bob = server.connect("bob").await;
+
+    let first = alice.send_message("frist").await.unwrap().accepted().await.unwrap().server_msg_id;
+    alice.send_message("gone soon").await.unwrap().accepted().await.unwrap();
+    alice.edit_message(first, "first").await.unwrap();
+    alice.delete_message(first + 1).await.unwrap();
+    alice.expect_message("the delete", |message| matches!(message, Message::Delete { .. })).await;
+    bob.react(first, "👍").await.unwrap();
+    alice.expect_message("the reaction", |message| matches!(message, Message::Reaction { .. })).await;
+    server.shutdown();
+
+    let server = TestServer::with_chat(ChatInstance::with_data_dir(&data_dir).unwrap()).await;
+    let alice = server.connect("alice").await;
+    let (_, messages) = alice.history(None, None).await.unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].content, "first");
+    assert!(messages[0].edited_at.is_some());
+    assert_eq!(messages[0].reactions.get("👍"), Some(&vec![bob.client_id()]));
+    assert!(messages[1].deleted);
+
+    // Ids carry on where the last run stopped
+    let next = alice.send_message("after the restart").await.unwrap().accepted().await.unwrap().server_msg_id;
+    assert_eq!(next, first + 2);
+    let _ = std::fs::remove_dir_all(&data_dir);
+}
@@ -0,0 +1,45 @@
+use client_lib::ClientError;
+use client_lib::testing::TestServer;
+use server::Role;
+use server::chat::ChatInstance;
+use server::filters::LinkBlocker;
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn rejected_messages_fail_with_the_reason_right_away() {
+    let chat = ChatInstance::new();
+    chat.add_filter(LinkBlocker).await;
+    let server = TestServer::with_chat(chat).await;
+    let alice = server.connect("alice").await;
+
+    let started = Instant::now();
+    let refused = alice.send_message("see https://example.com").await.unwrap().accepted().await;
+    match refused {
+        Err(ClientError::MessageRejected(reason)) => assert_eq!(reason, "Links are not allowed here"),
+        other => panic!("expected a rejection, got {:?}", other),
+    }
+    assert!(started.elapsed() < Duration::from_secs(2), "the rejection waited for the ack timeout");
+}
+
+#[tokio::test]
+async fn rejected_messages_do_not_start_the_slow_mode_wait() {
+    let chat = ChatInstance::new();
+    chat.add_filter(LinkBlocker).await;
+    let server = TestServer::with_chat(chat).await;
+    let mut owner = server.connect("owner").await;
+    server.chat().assign_role(owner.client_id(), None, Role::Owner).await.unwrap();
+    let alice = server.connect("alice").await;
+
+    owner.send_command("/slowmode 1h").await.unwrap();
+    owner.expect_system("turned on slow mode").await;
+
+    let refused = alice.send_message("www.example.com").await.unwrap().accepted().await;
+    assert!(matches!(refused, Err(ClientError::MessageRejected(_))));
+    alice.send_message("no link this time").await.unwrap().accepted().await.unwrap();
+
+    let throttled = alice.send_message("and again").await.unwrap().accepted().await;
+    match throttled {
+        Err(ClientError::MessageRejected(reason)) => assert!(reason.starts_with("Slow mode is on in #general")),
+        other => panic!("expected slow mode to apply, got {:?}", other),
+    }
+}
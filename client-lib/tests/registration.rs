@@ -0,0 +1,177 @@
+use client_lib::ClientError;
+use client_lib::testing::TestServer;
+use server::chat::ChatInstance;
+use server::mentions::default_nickname;
+use server::{Message, PresenceEvent, Role};
+use std::net::IpAddr;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, Lines};
+use tokio::net::TcpStream;
+use uuid::Uuid;
+
+// Logs in with a new id over a pipe the server sees as coming from `address`, and returns the
+// connection with the first message it got back
+async fn log_in_from(chat: &ChatInstance, address: IpAddr, nickname: &str) -> (Lines<BufReader<DuplexStream>>, Message) {
+    let (client_end, server_end) = tokio::io::duplex(4096);
+    let chat = chat.clone();
+    tokio::spawn(async move { chat.handle_connection_from(server_end, address).await });
+
+    let mut lines = BufReader::new(client_end).lines();
+    let log = serde_json::to_string(&Message::log(Uuid::new_v4(), false, Some(nickname), None)).unwrap();
+    lines.get_mut().get_mut().write_all(format!("{}\n", log).as_bytes()).await.unwrap();
+    let first = lines.next_line().await.unwrap().expect("the server hung up without answering");
+    (lines, serde_json::from_str(&first).unwrap())
+}
+
+#[tokio::test]
+async fn registered_clients_get_a_token_and_start_in_general_after_the_motd() {
+    let chat = ChatInstance::new();
+    chat.set_motd(Some("Be nice".to_string())).await;
+    let server = TestServer::with_chat(chat).await;
+    let mut alice = server.connect("alice").await;
+
+    let Message::Credentials { token } = alice.expect_message("any message", |_| true).await else {
+        panic!("a new id is issued its token first");
+    };
+    assert_eq!(alice.token().await, Some(token));
+    let motd = alice.expect_message("any message", |_| true).await;
+    assert!(matches!(motd, Message::System { content } if content == "Be nice"));
+    let entered = alice.expect_message("any message", |_| true).await;
+    assert!(matches!(entered, Message::RoomChanged { room, .. } if room == "general"));
+
+    assert_eq!(server.chat().nickname(&alice.client_id()).await.as_deref(), Some("alice"));
+    assert_eq!(server.chat().current_room(&alice.client_id()).await.as_deref(), Some("general"));
+}
+
+#[tokio::test]
+async fn others_see_a_joined_presence() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect("alice").await;
+    let bob = server.connect("bob").await;
+
+    let joined = alice
+        .expect_message("bob joining", |message| matches!(message, Message::Presence { nickname, .. } if nickname == "bob"))
+        .await;
+    let Message::Presence { client_id, event, .. } = joined else { unreachable!() };
+    assert_eq!(client_id, bob.client_id());
+    assert_eq!(event, PresenceEvent::Joined);
+}
+
+#[tokio::test]
+async fn taken_or_invalid_nicknames_fall_back_to_the_short_id() {
+    let server = TestServer::start().await;
+    let _alice = server.connect("alice").await;
+    let impostor = server.connect("ALICE").await;
+    let spaced = server.connect("not a nickname").await;
+
+    for client in [&impostor, &spaced] {
+        let nickname = server.chat().nickname(&client.client_id()).await;
+        assert_eq!(nickname, Some(default_nickname(&client.client_id())));
+    }
+}
+
+#[tokio::test]
+async fn banned_clients_are_refused_with_the_reason() {
+    let server = TestServer::start().await;
+    let owner = server.connect("owner").await;
+    server.chat().assign_role(owner.client_id(), None, Role::Owner).await.unwrap();
+    let mut mallory = server.connect("mallory").await;
+
+    owner.send_command("/ban mallory spam").await.unwrap();
+    mallory.expect_system("banned you from the server: spam").await;
+    mallory.expect_disconnected().await;
+
+    let again = server.builder().client_id(mallory.client_id()).connect().await;
+    match again {
+        Err(ClientError::Rejected(reason)) => assert_eq!(reason, "You are banned from this server"),
+        other => panic!("expected a rejection, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn bans_also_refuse_new_ids_from_the_same_address_until_lifted() {
+    let server = TestServer::start().await;
+    let mut owner = server.connect("owner").await;
+    server.chat().assign_role(owner.client_id(), None, Role::Owner).await.unwrap();
+    let address: IpAddr = "203.0.113.7".parse().unwrap();
+    let (_mallory, _) = log_in_from(server.chat(), address, "mallory").await;
+    let online = server.chat().online_clients().await;
+    let mallory = online.iter().find(|member| member.nickname == "mallory").unwrap().client_id;
+
+    owner.send_command("/ban mallory").await.unwrap();
+    owner.expect_message("mallory leaving", |message| {
+        matches!(message, Message::Presence { client_id, event: PresenceEvent::Left, .. } if *client_id == mallory)
+    })
+    .await;
+
+    let (_, refused) = log_in_from(server.chat(), address, "mallory2").await;
+    assert!(matches!(refused, Message::System { content } if content == "You are banned from this server"));
+    let (_, elsewhere) = log_in_from(server.chat(), "203.0.113.8".parse().unwrap(), "bystander").await;
+    assert!(matches!(elsewhere, Message::Credentials { .. }));
+
+    owner.send_command(&format!("/unban {}", mallory)).await.unwrap();
+    owner.expect_system("is no longer banned").await;
+    let (_, admitted) = log_in_from(server.chat(), address, "mallory3").await;
+    assert!(matches!(admitted, Message::Credentials { .. }));
+}
+
+#[tokio::test]
+async fn anything_but_log_first_closes_the_connection() {
+    let server = TestServer::start().await;
+    let mut stream = TcpStream::connect(server.address()).await.unwrap();
+    let chat = serde_json::to_string(&Message::system("hello")).unwrap();
+    stream.write_all(format!("{}\n", chat).as_bytes()).await.unwrap();
+
+    let mut line = String::new();
+    let read = BufReader::new(stream).read_line(&mut line).await.unwrap();
+    assert_eq!(read, 0, "expected the server to hang up, got {:?}", line);
+    assert!(server.chat().online_clients().await.is_empty());
+}
+
+#[tokio::test]
+async fn privileged_ids_cannot_be_taken_over() {
+    let server = TestServer::start().await;
+    let owner = server.connect("owner").await;
+    let owner_id = owner.client_id();
+    server.chat().assign_role(owner_id, None, Role::Owner).await.unwrap();
+    let token = owner.token().await.expect("the owner was issued a token");
+
+    // Not while the owner is connected, even with the owner's token
+    match server.builder().client_id(owner_id).nickname("mallory").token(token.clone()).connect().await {
+        Err(ClientError::Rejected(reason)) => assert!(reason.contains("already connected"), "{}", reason),
+        other => panic!("expected a rejection, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!(server.chat().nickname(&owner_id).await.as_deref(), Some("owner"));
+
+    // Nor once the owner left, without the owner's token
+    let mut watcher = server.connect("watcher").await;
+    drop(owner);
+    watcher
+        .expect_message("the owner leaving", |message| matches!(message, Message::Presence { event: PresenceEvent::Left, .. }))
+        .await;
+    for attempt in [None, Some("guessed".to_string())] {
+        let mut builder = server.builder().client_id(owner_id);
+        if let Some(token) = attempt {
+            builder = builder.token(token);
+        }
+        match builder.connect().await {
+            Err(ClientError::Rejected(reason)) => assert!(reason.contains("token"), "{}", reason),
+            other => panic!("expected a rejection, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    // The owner comes back with it and keeps the role
+    let owner = server.connect_with(server.builder().client_id(owner_id).token(token)).await;
+    assert_eq!(server.chat().role_in(&owner.client_id(), "general").await, Role::Owner);
+}
+
+#[tokio::test]
+async fn ids_issued_ahead_of_time_need_their_token() {
+    let server = TestServer::start().await;
+    let owner_id = uuid::Uuid::new_v4();
+    let token = server.chat().issue_credential(owner_id).await.unwrap().unwrap();
+    assert!(server.chat().issue_credential(owner_id).await.unwrap().is_none());
+
+    assert!(matches!(server.builder().client_id(owner_id).connect().await, Err(ClientError::Rejected(_))));
+    let owner = server.connect_with(server.builder().client_id(owner_id).token(token.clone())).await;
+    assert_eq!(owner.token().await, Some(token));
+}
@@ -0,0 +1,99 @@
+use client_lib::{ClientError, ClientEvent};
+use client_lib::testing::{TestClient, TestServer};
+use server::Message;
+use std::time::Duration;
+
+async fn join(client: &mut TestClient, room: &str) {
+    client.send_command(&format!("/join {}", room)).await.unwrap();
+    client
+        .expect_message("the room change", |message| matches!(message, Message::RoomChanged { room: joined, .. } if joined == room))
+        .await;
+}
+
+#[tokio::test]
+async fn threads_can_only_be_read_from_their_room() {
+    let server = TestServer::start().await;
+    let alice = server.connect("alice").await;
+    let mut bob = server.connect("bob").await;
+
+    let root = alice.send_message("general plans").await.unwrap().accepted().await.unwrap().server_msg_id;
+    let (thread_root, messages) = bob.thread(root).await.unwrap();
+    assert_eq!(thread_root, root);
+    assert_eq!(messages.len(), 1);
+
+    join(&mut bob, "ops").await;
+    match bob.thread(root).await {
+        Err(ClientError::Refused(reason)) => assert!(reason.contains("not in your room"), "{}", reason),
+        other => panic!("expected a refusal, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn edits_deletes_and_reactions_stay_in_the_messages_room() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect("alice").await;
+    let mut bob = server.connect("bob").await;
+    let mut carol = server.connect("carol").await;
+    join(&mut alice, "ops").await;
+    join(&mut bob, "ops").await;
+
+    let sent = alice.send_message("deploying now").await.unwrap().accepted().await.unwrap().server_msg_id;
+    bob.expect_chat("deploying now").await;
+
+    alice.edit_message(sent, "deploying at noon").await.unwrap();
+    bob.react(sent, "👍").await.unwrap();
+    alice.delete_message(sent).await.unwrap();
+    alice
+        .expect_message("the reaction", |message| matches!(message, Message::Reaction { room, .. } if room == "ops"))
+        .await;
+    bob.expect_message("the edit", |message| matches!(message, Message::Edit { room, .. } if room == "ops"))
+        .await;
+    bob.expect_message("the delete", |message| matches!(message, Message::Delete { room, .. } if room == "ops"))
+        .await;
+
+    // Carol in #general saw none of it
+    carol.send_message("marker").await.unwrap().accepted().await.unwrap();
+    let mut unexpected = Vec::new();
+    while let Some(event) = carol.next_event(Duration::from_millis(200)).await {
+        if let ClientEvent::Message(
+            message @ (Message::Edit { .. } | Message::Delete { .. } | Message::Reaction { .. }),
+        ) = event
+        {
+            unexpected.push(message);
+        }
+    }
+    assert!(unexpected.is_empty(), "carol received {:?}", unexpected);
+}
+
+#[tokio::test]
+async fn messages_from_other_rooms_cannot_be_reacted_to() {
+    let server = TestServer::start().await;
+    let alice = server.connect("alice").await;
+    let mut bob = server.connect("bob").await;
+
+    let sent = alice.send_message("general news").await.unwrap().accepted().await.unwrap().server_msg_id;
+    join(&mut bob, "ops").await;
+    bob.react(sent, "👍").await.unwrap();
+    bob.expect_system("not in your room").await;
+}
+
+#[tokio::test]
+async fn read_receipts_need_the_message_in_the_readers_room() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect("alice").await;
+    let mut bob = server.connect("bob").await;
+    let sent = alice.send_message("read me").await.unwrap().accepted().await.unwrap().server_msg_id;
+    bob.expect_chat("read me").await;
+
+    join(&mut bob, "ops").await;
+    bob.mark_read(sent).await.unwrap();
+    bob.mark_read(sent + 1000).await.unwrap();
+    alice.expect_no_message(Duration::from_millis(200), |message| matches!(message, Message::ReadReceipt { .. })).await;
+
+    join(&mut bob, "general").await;
+    bob.mark_read(sent).await.unwrap();
+    let receipt = alice
+        .expect_message("the receipt", |message| matches!(message, Message::ReadReceipt { .. }))
+        .await;
+    assert!(matches!(receipt, Message::ReadReceipt { message_id, client_id, .. } if message_id == sent && client_id == bob.client_id()));
+}
@@ -0,0 +1,71 @@
+use client_lib::testing::TestServer;
+use server::Message;
+use server::chat::ChatInstance;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines};
+use uuid::Uuid;
+
+// Registers as `nickname` over any stream and returns its lines once the client is in #general
+async fn register<S: AsyncRead + AsyncWrite + Unpin>(stream: S, nickname: &str) -> (Lines<BufReader<S>>, Uuid) {
+    let client_id = Uuid::new_v4();
+    let mut lines = BufReader::new(stream).lines();
+    send(&mut lines, &Message::log(client_id, false, Some(nickname), None)).await;
+    while let Some(line) = lines.next_line().await.unwrap() {
+        if matches!(serde_json::from_str(&line).unwrap(), Message::RoomChanged { .. }) {
+            return (lines, client_id);
+        }
+    }
+    panic!("the server hung up during registration");
+}
+
+async fn send<S: AsyncRead + AsyncWrite + Unpin>(lines: &mut Lines<BufReader<S>>, message: &Message) {
+    let json = format!("{}\n", serde_json::to_string(message).unwrap());
+    lines.get_mut().get_mut().write_all(json.as_bytes()).await.unwrap();
+}
+
+fn serve(chat: &ChatInstance, stream: impl server::Transport) {
+    let chat = chat.clone();
+    tokio::spawn(async move { chat.handle_connection(stream).await });
+}
+
+#[tokio::test]
+async fn duplex_clients_share_rooms_with_tcp_clients() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect("alice").await;
+
+    let (client_end, server_end) = tokio::io::duplex(4096);
+    serve(server.chat(), server_end);
+    let (mut pipe, pipe_id) = register(client_end, "pipe").await;
+    assert_eq!(server.chat().nickname(&pipe_id).await.as_deref(), Some("pipe"));
+
+    send(&mut pipe, &Message::chat(pipe_id, "over a pipe")).await;
+    assert_eq!(alice.expect_chat_from(pipe_id).await, "over a pipe");
+
+    alice.send_message("over tcp").await.unwrap();
+    while let Some(line) = pipe.next_line().await.unwrap() {
+        if let Message::Chat { content, .. } = serde_json::from_str(&line).unwrap() {
+            assert_eq!(content, "over tcp");
+            return;
+        }
+    }
+    panic!("the server hung up before relaying the chat");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn unix_socket_clients_are_served() {
+    let server = TestServer::start().await;
+    let mut alice = server.connect("alice").await;
+
+    let path = std::env::temp_dir().join(format!("chat-{}.sock", Uuid::new_v4().simple()));
+    let listener = tokio::net::UnixListener::bind(&path).unwrap();
+    let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+    let (accepted, _) = listener.accept().await.unwrap();
+    serve(server.chat(), accepted);
+
+    let (mut local, local_id) = register(stream, "local").await;
+    send(&mut local, &Message::chat(local_id, "over a socket")).await;
+    assert_eq!(alice.expect_chat_from(local_id).await, "over a socket");
+
+    drop(local);
+    let _ = std::fs::remove_file(&path);
+}
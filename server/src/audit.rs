@@ -0,0 +1,584 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use crate::chat::{ClientId, RoomId};
+use crate::messages::{Message, MessageId};
+use crate::roles::Role;
+use crate::storage::{load_json, save_json};
+
+// `prev_hash` of the first entry
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+// AuthFailed entries recorded per source and window, the rest are only counted
+pub const MAX_AUTH_FAILURES_PER_WINDOW: u32 = 5;
+pub const AUTH_FAILURE_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AuditEvent {
+    Registered {
+        nickname: String,
+    },
+    // The actor is the client id that was refused or overstepped its permissions
+    AuthFailed {
+        reason: String,
+    },
+    Muted {
+        target: ClientId,
+        room: RoomId,
+        seconds: u64,
+    },
+    Unmuted {
+        target: ClientId,
+        room: RoomId,
+    },
+    // `room: None` is a disconnect from the server
+    Kicked {
+        target: ClientId,
+        room: Option<RoomId>,
+        reason: Option<String>,
+    },
+    Banned {
+        target: ClientId,
+        reason: Option<String>,
+    },
+    Unbanned {
+        target: ClientId,
+    },
+    SlowMode {
+        room: RoomId,
+        seconds: Option<u64>,
+    },
+    RoleGranted {
+        target: ClientId,
+        role: Role,
+        room: Option<RoomId>,
+    },
+    RoleRevoked {
+        target: ClientId,
+        room: Option<RoomId>,
+    },
+    MessageDeleted {
+        message_id: MessageId,
+        author: ClientId,
+        room: RoomId,
+    },
+}
+
+impl AuditEvent {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AuditEvent::Registered { .. } => "Registered",
+            AuditEvent::AuthFailed { .. } => "AuthFailed",
+            AuditEvent::Muted { .. } => "Muted",
+            AuditEvent::Unmuted { .. } => "Unmuted",
+            AuditEvent::Kicked { .. } => "Kicked",
+            AuditEvent::Banned { .. } => "Banned",
+            AuditEvent::Unbanned { .. } => "Unbanned",
+            AuditEvent::SlowMode { .. } => "SlowMode",
+            AuditEvent::RoleGranted { .. } => "RoleGranted",
+            AuditEvent::RoleRevoked { .. } => "RoleRevoked",
+            AuditEvent::MessageDeleted { .. } => "MessageDeleted",
+        }
+    }
+
+    // The client acted upon, if any
+    pub fn target(&self) -> Option<ClientId> {
+        match self {
+            AuditEvent::Muted { target, .. }
+            | AuditEvent::Unmuted { target, .. }
+            | AuditEvent::Kicked { target, .. }
+            | AuditEvent::Banned { target, .. }
+            | AuditEvent::Unbanned { target }
+            | AuditEvent::RoleGranted { target, .. }
+            | AuditEvent::RoleRevoked { target, .. } => Some(*target),
+            AuditEvent::MessageDeleted { author, .. } => Some(*author),
+            AuditEvent::Registered { .. } | AuditEvent::AuthFailed { .. } | AuditEvent::SlowMode { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub timestamp: i64,
+    pub actor: ClientId,
+    pub event: AuditEvent,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let details = serde_json::to_string(&self.event).map_err(|_| fmt::Error)?;
+        write!(f, "#{} at {} by {}: {}", self.seq, self.timestamp, self.actor, details)
+    }
+}
+
+// The hashed part of an entry, serialized the same way on write and on verify
+#[derive(Serialize)]
+struct Hashed<'a> {
+    seq: u64,
+    timestamp: i64,
+    actor: &'a ClientId,
+    event: &'a AuditEvent,
+    prev_hash: &'a str,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self) -> Result<String> {
+        let hashed = Hashed {
+            seq: self.seq,
+            timestamp: self.timestamp,
+            actor: &self.actor,
+            event: &self.event,
+            prev_hash: &self.prev_hash,
+        };
+        Ok(format!("{:x}", Sha256::digest(serde_json::to_vec(&hashed)?)))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    pub actor: Option<ClientId>,
+    pub target: Option<ClientId>,
+    pub kind: Option<String>,
+    pub since: Option<i64>,
+    // Keeps only the most recent matches
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.actor.is_none_or(|actor| entry.actor == actor)
+            && self.target.is_none_or(|target| entry.event.target() == Some(target))
+            && self.kind.as_ref().is_none_or(|kind| entry.event.kind().eq_ignore_ascii_case(kind))
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+    }
+
+    // The matching entries in log order, cut down to `limit`
+    pub fn select<'a>(&self, entries: &'a [AuditEntry]) -> Vec<&'a AuditEntry> {
+        let matching: Vec<&AuditEntry> = entries.iter().filter(|entry| self.matches(entry)).collect();
+        let skip = self.limit.map_or(0, |limit| matching.len().saturating_sub(limit));
+        matching.into_iter().skip(skip).collect()
+    }
+}
+
+// The entry count and last hash, saved next to the log after every entry. The chain alone can't
+// tell a log whose last lines were cut off from one that was never longer; the head can, as long
+// as it isn't rewritten along with the log, so keep a copy elsewhere to verify against
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditHead {
+    pub entries: u64,
+    pub hash: String,
+}
+
+impl AuditHead {
+    pub fn of(entries: &[AuditEntry]) -> Self {
+        Self {
+            entries: entries.len() as u64,
+            hash: entries.last().map_or(GENESIS_HASH, |last| last.hash.as_str()).to_string(),
+        }
+    }
+}
+
+// `audit.jsonl` keeps its head in `audit.head.json`
+pub fn head_path(log_path: &Path) -> PathBuf {
+    log_path.with_extension("head.json")
+}
+
+// Append-only, hash-chained record of administrative and security events,
+// one JSON object per line when backed by a file. Each entry's hash covers the
+// previous entry's hash, so editing, removing or reordering lines breaks the chain,
+// and the saved `AuditHead` catches lines removed from the end.
+pub struct AuditLog {
+    entries: Vec<AuditEntry>,
+    path: Option<PathBuf>,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+impl AuditLog {
+    pub fn in_memory() -> Self {
+        Self {
+            entries: Vec::new(),
+            path: None,
+        }
+    }
+
+    // Refuses to extend a log whose chain is already broken or that no longer ends at its head.
+    // A log without a head, e.g. from before heads were kept, gets one. What a crash in the middle
+    // of `record` leaves behind is repaired: an unfinished last line is cut off, and a last entry
+    // that was appended but not yet counted in the head is kept
+    pub fn open(path: PathBuf) -> Result<Self> {
+        drop_unfinished_entry(&path)?;
+        let entries = read_entries(&path)?;
+        verify_chain(&entries)?;
+        let head_path = head_path(&path);
+        match load_json::<Option<AuditHead>>(&head_path)? {
+            Some(head) if entries.len() as u64 == head.entries + 1 => {
+                verify_head(&entries[..entries.len() - 1], &head)?;
+                save_json(&head_path, &AuditHead::of(&entries))?;
+            }
+            Some(head) => verify_head(&entries, &head)?,
+            None => save_json(&head_path, &AuditHead::of(&entries))?,
+        }
+        Ok(Self {
+            entries,
+            path: Some(path),
+        })
+    }
+
+    pub fn record(&mut self, actor: ClientId, event: AuditEvent) -> Result<AuditEntry> {
+        let (seq, prev_hash) = match self.entries.last() {
+            Some(last) => (last.seq + 1, last.hash.clone()),
+            None => (1, GENESIS_HASH.to_string()),
+        };
+        let mut entry = AuditEntry {
+            seq,
+            timestamp: Message::timestamp(),
+            actor,
+            event,
+            prev_hash,
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash()?;
+
+        if let Some(path) = &self.path {
+            let line = format!("{}\n", serde_json::to_string(&entry)?);
+            let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+            let length = file.metadata()?.len();
+            // Take back a partly written line, so later entries don't end up behind it
+            if let Err(e) = file.write_all(line.as_bytes()) {
+                let _ = file.set_len(length);
+                return Err(e.into());
+            }
+        }
+        self.entries.push(entry.clone());
+        if let Some(path) = &self.path {
+            save_json(&head_path(path), &AuditHead::of(&self.entries))?;
+        }
+        Ok(entry)
+    }
+
+    pub fn entries(&self) -> &[AuditEntry] {
+        &self.entries
+    }
+
+    pub fn query(&self, query: &AuditQuery) -> Vec<AuditEntry> {
+        query.select(&self.entries).into_iter().cloned().collect()
+    }
+
+    // Checks the file on disk, not the entries held in memory, so edits made
+    // behind the server's back are caught. Its head must still match what was written
+    pub fn verify(&self) -> Result<usize> {
+        match &self.path {
+            Some(path) => verify_file(path, &AuditHead::of(&self.entries)),
+            None => {
+                verify_chain(&self.entries)?;
+                Ok(self.entries.len())
+            }
+        }
+    }
+}
+
+// Entries are written with their newline, so anything after the last one is from an append cut short
+fn drop_unfinished_entry(path: &Path) -> Result<()> {
+    let contents = match std::fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let complete = contents.iter().rposition(|byte| *byte == b'\n').map_or(0, |newline| newline + 1);
+    if complete < contents.len() {
+        eprintln!("Dropping an unfinished entry at the end of {}", path.display());
+        std::fs::OpenOptions::new().write(true).open(path)?.set_len(complete as u64)?;
+    }
+    Ok(())
+}
+
+// Where refusals come from: the peer address for refused logins where there is one, the client otherwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum AuthSource {
+    Address(IpAddr),
+    Client(ClientId),
+}
+
+struct FailureWindow {
+    started: Instant,
+    recorded: u32,
+    suppressed: u32,
+}
+
+// Keeps anyone who can reach the server from filling the audit log with refusals
+pub(crate) struct AuthFailureLimit {
+    max: u32,
+    window: Duration,
+    sources: HashMap<AuthSource, FailureWindow>,
+}
+
+impl Default for AuthFailureLimit {
+    fn default() -> Self {
+        Self::new(MAX_AUTH_FAILURES_PER_WINDOW, AUTH_FAILURE_WINDOW)
+    }
+}
+
+impl AuthFailureLimit {
+    pub(crate) fn new(max: u32, window: Duration) -> Self {
+        Self { max: max.max(1), window, sources: HashMap::new() }
+    }
+
+    // None if this refusal should not be recorded, otherwise how many from the same source
+    // were left out since the last one that was
+    pub(crate) fn admit(&mut self, source: AuthSource) -> Option<u32> {
+        let now = Instant::now();
+        let window = self.window;
+        // Sources with refusals left out are kept until their next one reports them
+        self.sources.retain(|_, failures| failures.suppressed > 0 || now.duration_since(failures.started) < window);
+
+        let failures = self.sources.entry(source).or_insert(FailureWindow { started: now, recorded: 0, suppressed: 0 });
+        if now.duration_since(failures.started) >= window {
+            failures.started = now;
+            failures.recorded = 0;
+        }
+        if failures.recorded >= self.max {
+            failures.suppressed += 1;
+            return None;
+        }
+        failures.recorded += 1;
+        Some(std::mem::take(&mut failures.suppressed))
+    }
+}
+
+pub fn read_entries(path: &Path) -> Result<Vec<AuditEntry>> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut entries = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => bail!("Line {} of the audit log is not a valid entry: {}", index + 1, e),
+        }
+    }
+    Ok(entries)
+}
+
+// Returns the number of entries checked
+pub fn verify_file(path: &Path, head: &AuditHead) -> Result<usize> {
+    let entries = read_entries(path)?;
+    verify_chain(&entries)?;
+    verify_head(&entries, head)?;
+    Ok(entries.len())
+}
+
+// For checking a log offline against its saved head, or a copy of it given as `head_path`
+pub fn verify_file_with_head(path: &Path, head_path: &Path) -> Result<usize> {
+    let Some(head) = load_json::<Option<AuditHead>>(head_path)? else {
+        bail!("{} is missing, the audit log can't be checked for truncation", head_path.display());
+    };
+    verify_file(path, &head)
+}
+
+// Entries that verify as a chain still have to end where the log was last written
+pub fn verify_head(entries: &[AuditEntry], head: &AuditHead) -> Result<()> {
+    let actual = AuditHead::of(entries);
+    if actual.entries < head.entries {
+        bail!("The audit log ends at entry {} but {} entries were written, it was truncated", actual.entries, head.entries);
+    }
+    if actual.entries > head.entries {
+        bail!("The audit log has {} entries but its head records {}", actual.entries, head.entries);
+    }
+    if actual.hash != head.hash {
+        bail!("The last audit entry is not the one that was written as entry {}", head.entries);
+    }
+    Ok(())
+}
+
+pub fn verify_chain(entries: &[AuditEntry]) -> Result<()> {
+    let mut prev_hash = GENESIS_HASH;
+    for (index, entry) in entries.iter().enumerate() {
+        let expected_seq = index as u64 + 1;
+        if entry.seq != expected_seq {
+            bail!("Audit entry {} has sequence number {}, entries are missing or reordered", expected_seq, entry.seq);
+        }
+        if entry.prev_hash != prev_hash {
+            bail!("Audit entry {} does not follow the entry before it", entry.seq);
+        }
+        if entry.compute_hash()? != entry.hash {
+            bail!("Audit entry {} was modified after it was written", entry.seq);
+        }
+        prev_hash = &entry.hash;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    // A log of `count` entries in a fresh directory, removed when the test's guard drops
+    struct TempLog {
+        dir: PathBuf,
+        path: PathBuf,
+    }
+
+    impl TempLog {
+        fn with_entries(count: usize) -> (Self, AuditLog) {
+            let dir = std::env::temp_dir().join(format!("audit-{}", Uuid::new_v4().simple()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("audit.jsonl");
+            let mut log = AuditLog::open(path.clone()).unwrap();
+            for n in 0..count {
+                log.record(Uuid::new_v4(), AuditEvent::Registered { nickname: format!("client{}", n) }).unwrap();
+            }
+            (Self { dir, path }, log)
+        }
+
+        fn lines(&self) -> Vec<String> {
+            std::fs::read_to_string(&self.path).unwrap().lines().map(str::to_string).collect()
+        }
+
+        fn write_lines(&self, lines: &[String]) {
+            std::fs::write(&self.path, lines.iter().map(|line| format!("{}\n", line)).collect::<String>()).unwrap();
+        }
+    }
+
+    impl Drop for TempLog {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn chain(count: usize) -> Vec<AuditEntry> {
+        let mut log = AuditLog::in_memory();
+        for _ in 0..count {
+            log.record(Uuid::new_v4(), AuditEvent::Unbanned { target: Uuid::new_v4() }).unwrap();
+        }
+        log.entries().to_vec()
+    }
+
+    #[test]
+    fn verify_chain_catches_edits_removals_and_reordering() {
+        let entries = chain(4);
+        verify_chain(&entries).unwrap();
+        verify_chain(&[]).unwrap();
+
+        let mut edited = entries.clone();
+        edited[1].actor = Uuid::new_v4();
+        assert!(verify_chain(&edited).unwrap_err().to_string().contains("modified"));
+
+        let mut removed = entries.clone();
+        removed.remove(1);
+        assert!(verify_chain(&removed).unwrap_err().to_string().contains("missing or reordered"));
+
+        let mut reordered = entries.clone();
+        reordered.swap(1, 2);
+        assert!(verify_chain(&reordered).is_err());
+
+        // Renumbering after a removal still leaves a gap in the chain
+        let mut renumbered = entries;
+        renumbered.remove(1);
+        for (index, entry) in renumbered.iter_mut().enumerate() {
+            entry.seq = index as u64 + 1;
+        }
+        assert!(verify_chain(&renumbered).is_err());
+    }
+
+    #[test]
+    fn entries_edited_in_the_middle_of_the_file_fail_verification() {
+        let (file, log) = TempLog::with_entries(3);
+        assert_eq!(log.verify().unwrap(), 3);
+
+        let mut lines = file.lines();
+        lines[1] = lines[1].replace("client1", "someone else");
+        file.write_lines(&lines);
+
+        assert!(log.verify().unwrap_err().to_string().contains("Audit entry 2 was modified"));
+        assert!(AuditLog::open(file.path.clone()).is_err());
+    }
+
+    #[test]
+    fn truncated_files_fail_verification_against_their_head() {
+        let (file, log) = TempLog::with_entries(3);
+        let mut lines = file.lines();
+        lines.pop();
+        file.write_lines(&lines);
+
+        // The remaining entries still form a valid chain
+        verify_chain(&read_entries(&file.path).unwrap()).unwrap();
+        assert!(log.verify().unwrap_err().to_string().contains("truncated"));
+        assert!(verify_file_with_head(&file.path, &head_path(&file.path)).is_err());
+        assert!(AuditLog::open(file.path.clone()).is_err());
+    }
+
+    #[test]
+    fn open_recovers_from_a_crash_while_recording() {
+        let (file, _) = TempLog::with_entries(3);
+        let head = head_path(&file.path);
+        let entries = read_entries(&file.path).unwrap();
+
+        // Appended, but the head was not updated yet
+        save_json(&head, &AuditHead::of(&entries[..2])).unwrap();
+        assert_eq!(AuditLog::open(file.path.clone()).unwrap().entries().len(), 3);
+        assert_eq!(verify_file_with_head(&file.path, &head).unwrap(), 3);
+
+        // Cut off in the middle of the line
+        let mut contents = std::fs::read_to_string(&file.path).unwrap();
+        contents.push_str("{\"seq\":4,\"timest");
+        std::fs::write(&file.path, contents).unwrap();
+        let mut reopened = AuditLog::open(file.path.clone()).unwrap();
+        reopened.record(Uuid::new_v4(), AuditEvent::Unbanned { target: Uuid::new_v4() }).unwrap();
+        assert_eq!(reopened.verify().unwrap(), 4);
+
+        // Two entries missing from the head is more than a crash can leave
+        save_json(&head, &AuditHead::of(&entries[..2])).unwrap();
+        assert!(AuditLog::open(file.path.clone()).is_err());
+    }
+
+    #[test]
+    fn auth_failures_are_limited_per_source_and_the_rest_counted() {
+        let mut limit = AuthFailureLimit::new(2, Duration::from_millis(20));
+        let (address, client) = (AuthSource::Address([10, 0, 0, 1].into()), AuthSource::Client(Uuid::new_v4()));
+
+        assert_eq!(limit.admit(address), Some(0));
+        assert_eq!(limit.admit(address), Some(0));
+        assert_eq!(limit.admit(address), None);
+        assert_eq!(limit.admit(address), None);
+        assert_eq!(limit.admit(client), Some(0));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(limit.admit(address), Some(2));
+        assert_eq!(limit.admit(address), Some(0));
+        assert_eq!(limit.sources.len(), 1);
+    }
+
+    #[test]
+    fn logs_without_a_head_get_one_and_a_missing_head_fails_offline_checks() {
+        let (file, _) = TempLog::with_entries(2);
+        let head = head_path(&file.path);
+        std::fs::remove_file(&head).unwrap();
+        assert!(verify_file_with_head(&file.path, &head).is_err());
+
+        let reopened = AuditLog::open(file.path.clone()).unwrap();
+        assert_eq!(verify_file_with_head(&file.path, &head).unwrap(), 2);
+        assert_eq!(reopened.verify().unwrap(), 2);
+    }
+}
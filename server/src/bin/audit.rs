@@ -0,0 +1,50 @@
+use anyhow::{Result, anyhow, bail};
+use std::path::PathBuf;
+use server::audit::{AuditQuery, head_path, read_entries, verify_file_with_head};
+
+const USAGE: &str = "Usage:
+  audit verify [--file PATH] [--head HEAD]
+  audit query [--actor ID] [--target ID] [--kind KIND] [--since TIMESTAMP] [--limit N] [--file PATH]
+
+PATH defaults to audit.jsonl in CHAT_DATA_DIR (default `data`), HEAD to the head saved next to it;
+point HEAD at a copy kept elsewhere to catch a log truncated along with its head";
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let command = args.next().unwrap_or_default();
+
+    let data_dir = std::env::var("CHAT_DATA_DIR").unwrap_or_else(|_| "data".to_string());
+    let mut path = PathBuf::from(data_dir).join("audit.jsonl");
+    let mut head = None;
+    let mut query = AuditQuery::default();
+
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| anyhow!("Missing value for {}\n\n{}", flag, USAGE))?;
+        match flag.as_str() {
+            "--file" => path = PathBuf::from(value),
+            "--head" => head = Some(PathBuf::from(value)),
+            "--actor" => query.actor = Some(value.parse()?),
+            "--target" => query.target = Some(value.parse()?),
+            "--kind" => query.kind = Some(value),
+            "--since" => query.since = Some(value.parse()?),
+            "--limit" => query.limit = Some(value.parse()?),
+            _ => bail!("Unknown option {}\n\n{}", flag, USAGE),
+        }
+    }
+
+    match command.as_str() {
+        "verify" => {
+            let head = head.unwrap_or_else(|| head_path(&path));
+            let count = verify_file_with_head(&path, &head)?;
+            println!("{}: {} entries, chain intact and ending at its head", path.display(), count);
+        }
+        "query" => {
+            // Matching entries are printed in their on-disk form so the output can be piped on
+            for entry in query.select(&read_entries(&path)?) {
+                println!("{}", serde_json::to_string(entry)?);
+            }
+        }
+        _ => bail!("{}", USAGE),
+    }
+    Ok(())
+}
@@ -1,19 +1,117 @@
-use anyhow::{Result};
-use std::collections::HashMap;
+use anyhow::{Result, anyhow, bail};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::path::Path;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 use uuid::Uuid;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
-use tokio::net::{TcpStream};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
 use tokio::sync::{broadcast, Mutex};
-use crate::messages::Message;
+use crate::audit::{AuditEntry, AuditEvent, AuditLog, AuditQuery, AuthFailureLimit, AuthSource};
+use crate::commands::{Command, CommandRegistry, is_command, parse_command};
+use crate::filters::{FilterContext, FilterPipeline, MessageFilter};
+use crate::history::{DEFAULT_HISTORY_CAPACITY, History, StoredMessage};
+use crate::identity::Credentials;
+use crate::mentions::{MAX_NICKNAME_LEN, default_nickname, is_valid_nickname, parse_mentions};
+use crate::moderation::{MAX_MUTE, MAX_SLOW_MODE, Moderation, format_duration};
+use crate::messages::{ClientMsgId, Message, MessageId, PresenceEvent, RoomSummary, UserInfo, UserStatus};
+use crate::roles::{Permission, Role, RoleStore};
+use crate::rooms::{MAX_DESCRIPTION_LEN, MAX_TOPIC_LEN, Room, Rooms, normalize_room_name};
 use tokio::sync::mpsc::{Sender, Receiver};
 
 pub type ClientId = Uuid;
-pub type ClientRegistry = Arc<Mutex<HashMap<ClientId, (Sender<Message>, Receiver<Message>)>>>;
+pub type RoomId = String;
 
+pub const DEFAULT_ROOM: &str = "general";
+
+pub fn default_room() -> RoomId {
+    DEFAULT_ROOM.to_string()
+}
+
+// The change was already delivered, so a failed save is only logged
+fn save_history(history: &History) {
+    if let Err(e) = history.save() {
+        eprintln!("Failed to save history: {}", e);
+    }
+}
+pub type ClientRegistry = Arc<Mutex<HashMap<ClientId, ClientEntry>>>;
+
+// Anything a client can reach the server over: TCP, Unix sockets, TLS, in-memory duplex pipes
+pub trait Transport: AsyncRead + AsyncWrite + Send + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Send + 'static> Transport for T {}
+
+const MAX_STATUS_TEXT_LEN: usize = 128;
+const MAX_EMOJI_LEN: usize = 32;
+const DEFAULT_HISTORY_PAGE: usize = 50;
+const MAX_HISTORY_PAGE: usize = 200;
+
+pub struct ClientEntry {
+    pub sender: Sender<Message>,
+    pub nickname: String,
+    pub status: UserStatus,
+    pub status_text: Option<String>,
+    pub room: RoomId,
+    // Where the client connected from, if the transport has an address
+    pub address: Option<IpAddr>,
+    // Tells this connection apart from a later one with the same id
+    connection: u64,
+}
+
+impl ClientEntry {
+    fn new(sender: Sender<Message>, nickname: String, address: Option<IpAddr>, connection: u64) -> Self {
+        Self {
+            sender,
+            nickname,
+            status: UserStatus::Online,
+            status_text: None,
+            room: DEFAULT_ROOM.to_string(),
+            address,
+            connection,
+        }
+    }
+
+    fn info(&self, client_id: ClientId) -> UserInfo {
+        UserInfo {
+            client_id,
+            nickname: self.nickname.clone(),
+            status: self.status,
+            status_text: self.status_text.clone(),
+        }
+    }
+}
+
+// A connection's tasks end with it, even when the connection future itself is cancelled
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+#[derive(Clone)]
 pub struct ChatInstance {
     clients: ClientRegistry,
+    next_connection: Arc<AtomicU64>,
     broadcast: broadcast::Sender<Message>,
+    history: Arc<Mutex<History>>,
+    roles: Arc<Mutex<RoleStore>>,
+    moderation: Arc<Mutex<Moderation>>,
+    credentials: Arc<Mutex<Credentials>>,
+    audit: Arc<Mutex<AuditLog>>,
+    auth_failures: Arc<Mutex<AuthFailureLimit>>,
+    filters: Arc<Mutex<FilterPipeline>>,
+    rooms: Arc<Mutex<Rooms>>,
+    motd: Arc<Mutex<Option<String>>>,
+    commands: Arc<Mutex<CommandRegistry>>,
+}
+
+impl Default for ChatInstance {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ChatInstance {
@@ -22,41 +120,676 @@ impl ChatInstance {
 
         Self {
             clients: Arc::new(Mutex::new(HashMap::new())),
-            broadcast: tx
+            next_connection: Arc::new(AtomicU64::new(1)),
+            broadcast: tx,
+            history: Arc::new(Mutex::new(History::default())),
+            roles: Arc::new(Mutex::new(RoleStore::in_memory())),
+            moderation: Arc::new(Mutex::new(Moderation::in_memory())),
+            credentials: Arc::new(Mutex::new(Credentials::in_memory())),
+            audit: Arc::new(Mutex::new(AuditLog::in_memory())),
+            auth_failures: Arc::new(Mutex::new(AuthFailureLimit::default())),
+            filters: Arc::new(Mutex::new(FilterPipeline::with_defaults())),
+            rooms: Arc::new(Mutex::new(Rooms::in_memory())),
+            motd: Arc::new(Mutex::new(None)),
+            commands: Arc::new(Mutex::new(CommandRegistry::with_builtins())),
         }
     }
 
-    pub async fn handle_connection(&self, stream: TcpStream) -> Result<()> {
-        self.handle_client_session(stream).await?;
+    // Keeps room settings in `rooms.json`, role assignments in `roles.json`, bans in `bans.json` and
+    // `address_bans.json`, client tokens in `credentials.json`, recent messages in `history.json` and
+    // moderation actions in `audit.jsonl` with its head in `audit.head.json`, and reads the MOTD from
+    // `motd.txt` if present
+    pub fn with_data_dir(data_dir: impl AsRef<Path>) -> Result<Self> {
+        let data_dir = data_dir.as_ref();
+        std::fs::create_dir_all(data_dir)?;
+
+        let mut chat = Self::new();
+        chat.rooms = Arc::new(Mutex::new(Rooms::load(data_dir.join("rooms.json"))?));
+        chat.roles = Arc::new(Mutex::new(RoleStore::load(data_dir.join("roles.json"))?));
+        chat.moderation = Arc::new(Mutex::new(Moderation::load(data_dir.join("bans.json"), data_dir.join("address_bans.json"))?));
+        chat.credentials = Arc::new(Mutex::new(Credentials::load(data_dir.join("credentials.json"))?));
+        chat.audit = Arc::new(Mutex::new(AuditLog::open(data_dir.join("audit.jsonl"))?));
+        let history = History::load(data_dir.join("history.json"), DEFAULT_HISTORY_CAPACITY)?;
+        chat.history = Arc::new(Mutex::new(history));
+        let motd = std::fs::read_to_string(data_dir.join("motd.txt")).ok();
+        chat.motd = Arc::new(Mutex::new(motd.filter(|motd| !motd.trim().is_empty())));
+        Ok(chat)
+    }
+
+    // Sent to every client as a System message right after it registers
+    pub async fn set_motd(&self, motd: Option<String>) {
+        *self.motd.lock().await = motd;
+    }
+
+    pub async fn handle_connection(&self, stream: impl Transport) -> Result<()> {
+        self.handle_client_session(stream, None).await?;
         Ok(())
     }
 
+    // For transports with a peer address, e.g. TCP, so bans can cover the address as well as the id
+    pub async fn handle_connection_from(&self, stream: impl Transport, address: IpAddr) -> Result<()> {
+        self.handle_client_session(stream, Some(address)).await?;
+        Ok(())
+    }
+
+    pub async fn online_clients(&self) -> Vec<UserInfo> {
+        self.list_clients(None).await
+    }
+
+    // Assigns a role without any permission check, e.g. to bootstrap the server owners
+    pub async fn assign_role(&self, client_id: ClientId, room: Option<&str>, role: Role) -> Result<()> {
+        self.roles.lock().await.assign(client_id, room, role)
+    }
+
+    // Claims an id ahead of its first connection, e.g. for seeded owners, and returns the token to
+    // hand to its client. None if the id is already claimed
+    pub async fn issue_credential(&self, client_id: ClientId) -> Result<Option<String>> {
+        self.credentials.lock().await.issue(client_id)
+    }
+
+    pub async fn role_in(&self, client_id: &ClientId, room: &str) -> Role {
+        self.roles.lock().await.role_in(client_id, room)
+    }
+
+    pub(crate) async fn require(&self, client_id: &ClientId, room: &str, permission: Permission) -> Result<()> {
+        let role = self.role_in(client_id, room).await;
+        if !role.can(permission) {
+            bail!("As a {} in #{} you are not allowed to do that", role, room);
+        }
+        Ok(())
+    }
+
+    // Acting on the server-wide role needs the permission server-wide, acting on a room needs it there
+    async fn role_in_scope(&self, client_id: &ClientId, room: Option<&str>) -> Role {
+        let roles = self.roles.lock().await;
+        match room {
+            Some(room) => roles.role_in(client_id, room),
+            None => roles.server_role(client_id),
+        }
+    }
+
+    pub(crate) async fn grant_role(&self, actor: ClientId, target: ClientId, role: Role, room: Option<&str>) -> Result<()> {
+        let room = room.map(|room| normalize_room_name(room).ok_or_else(|| anyhow!("Invalid room name {}", room))).transpose()?;
+        self.authorize_role_change(actor, target, room.as_deref()).await?;
+        if role > self.role_in_scope(&actor, room.as_deref()).await {
+            bail!("You cannot grant a role above your own");
+        }
+
+        self.roles.lock().await.assign(target, room.as_deref(), role)?;
+        self.record_audit(actor, AuditEvent::RoleGranted { target, role, room: room.clone() }).await;
+        let _ = self.broadcast.send(Message::RoleChanged { client_id: target, role: Some(role), room });
+        Ok(())
+    }
+
+    pub(crate) async fn revoke_role(&self, actor: ClientId, target: ClientId, room: Option<&str>) -> Result<()> {
+        let room = room.map(|room| normalize_room_name(room).ok_or_else(|| anyhow!("Invalid room name {}", room))).transpose()?;
+        self.authorize_role_change(actor, target, room.as_deref()).await?;
+
+        if !self.roles.lock().await.revoke(&target, room.as_deref())? {
+            bail!("There is no role assignment to revoke");
+        }
+        self.record_audit(actor, AuditEvent::RoleRevoked { target, room: room.clone() }).await;
+        let _ = self.broadcast.send(Message::RoleChanged { client_id: target, role: None, room });
+        Ok(())
+    }
+
+    // Only owners may change roles, and only of clients ranked below them
+    async fn authorize_role_change(&self, actor: ClientId, target: ClientId, room: Option<&str>) -> Result<()> {
+        let actor_role = self.role_in_scope(&actor, room).await;
+        if !actor_role.can(Permission::ManageRoles) {
+            return Err(self.denied(actor, "You are not allowed to manage roles here").await);
+        }
+        if actor != target && self.role_in_scope(&target, room).await >= actor_role {
+            return Err(self.denied(actor, "You cannot change the role of someone ranked as high as you").await);
+        }
+        Ok(())
+    }
+
+    // Server-wide permissions come from the server role only, never from a room assignment
+    pub(crate) async fn require_server_permission(&self, client_id: ClientId, permission: Permission) -> Result<()> {
+        let role = self.role_in_scope(&client_id, None).await;
+        if !role.can(permission) {
+            return Err(self.denied(client_id, &format!("As a {} you are not allowed to do that here", role)).await);
+        }
+        Ok(())
+    }
+
+    // Records the refusal in the audit log and returns it as the error for the actor
+    async fn denied(&self, actor: ClientId, reason: &str) -> anyhow::Error {
+        self.record_auth_failure(AuthSource::Client(actor), actor, reason).await;
+        anyhow!(reason.to_string())
+    }
+
+    // Rate limited per source, see AuthFailureLimit
+    async fn record_auth_failure(&self, source: AuthSource, actor: ClientId, reason: &str) {
+        let reason = match self.auth_failures.lock().await.admit(source) {
+            None => return,
+            Some(0) => reason.to_string(),
+            Some(left_out) => format!("{} ({} earlier refusals from the same source were not recorded)", reason, left_out),
+        };
+        self.record_audit(actor, AuditEvent::AuthFailed { reason }).await;
+    }
+
+    pub async fn audit_entries(&self, query: &AuditQuery) -> Vec<AuditEntry> {
+        self.audit.lock().await.query(query)
+    }
+
+    // Number of entries whose hash chain checked out
+    pub async fn verify_audit_log(&self) -> Result<usize> {
+        self.audit.lock().await.verify()
+    }
+
+    // Appends a filter that runs after the ones already installed
+    pub async fn add_filter(&self, filter: impl MessageFilter + 'static) {
+        self.filters.lock().await.push(filter);
+    }
+
+    // Replaces the whole pipeline, including the default length and spam filters
+    pub async fn set_filters(&self, pipeline: FilterPipeline) {
+        *self.filters.lock().await = pipeline;
+    }
+
+    async fn apply_filters(&self, sender: ClientId, room: &str, content: &str, edit: bool) -> Result<String> {
+        let context = FilterContext { sender, room, edit };
+        self.filters.lock().await.run(&context, content).map_err(|reason| anyhow!(reason))
+    }
+
+    // Adds a slash command, replacing any built-in with the same name
+    pub async fn register_command(&self, command: impl Command + 'static) {
+        self.commands.lock().await.register(command);
+    }
+
+    pub(crate) async fn commands(&self) -> Vec<Arc<dyn Command>> {
+        self.commands.lock().await.all()
+    }
+
+    pub(crate) async fn command(&self, name: &str) -> Option<Arc<dyn Command>> {
+        self.commands.lock().await.get(name)
+    }
+
+    pub async fn send_system(&self, client_id: &ClientId, content: &str) {
+        self.send_direct(client_id, Message::system(content)).await;
+    }
+
+    pub async fn nickname(&self, client_id: &ClientId) -> Option<String> {
+        self.clients.lock().await.get(client_id).map(|entry| entry.nickname.clone())
+    }
+
+    pub(crate) async fn client_by_nickname(&self, nickname: &str) -> Option<ClientId> {
+        self.clients
+            .lock()
+            .await
+            .iter()
+            .find(|(_, entry)| entry.nickname.eq_ignore_ascii_case(nickname.trim_start_matches('@')))
+            .map(|(client_id, _)| *client_id)
+    }
+
+    pub async fn current_room(&self, client_id: &ClientId) -> Option<RoomId> {
+        self.clients.lock().await.get(client_id).map(|entry| entry.room.clone())
+    }
+
+    pub async fn room(&self, room: &str) -> Room {
+        self.rooms.lock().await.get(room)
+    }
+
+    fn room_changed(room: RoomId, settings: Room) -> Message {
+        Message::RoomChanged {
+            room,
+            topic: settings.topic,
+            description: settings.description,
+        }
+    }
+
     // Client registration
-    async fn register_client(&self, client_id: ClientId) -> ClientId {
+    // Refuses banned clients and ids claimed by someone else, the error is the reason sent to the client.
+    // Clients that may ban are let in from a banned address once their token checks out
+    async fn admit(&self, client_id: ClientId, token: Option<&str>, address: Option<IpAddr>) -> Result<()> {
+        if self.moderation.lock().await.is_banned(&client_id) {
+            bail!("You are banned from this server");
+        }
+        self.credentials.lock().await.verify(&client_id, token)?;
+        if let Some(address) = address
+            && self.moderation.lock().await.is_address_banned(&address)
+            && !self.role_in_scope(&client_id, None).await.can(Permission::Ban)
+        {
+            bail!("You are banned from this server");
+        }
+        Ok(())
+    }
+
+    // Returns the client's queue and the number of this connection, for `unregister_client`
+    async fn register_client(&self, client_id: ClientId, nickname: Option<String>, address: Option<IpAddr>) -> Result<(Receiver<Message>, u64)> {
         let (sender, receiver) = tokio::sync::mpsc::channel(100);
         let mut clients = self.clients.lock().await;
-        clients.insert(client_id, (sender, receiver));
-        client_id
+        if clients.contains_key(&client_id) {
+            bail!("Client {} is already connected", client_id);
+        }
+        let nickname = nickname
+            .filter(|nickname| is_valid_nickname(nickname) && !Self::nickname_taken(&clients, nickname))
+            .unwrap_or_else(|| default_nickname(&client_id));
+        let _ = self.broadcast.send(Message::presence(client_id, &nickname, PresenceEvent::Joined));
+        let connection = self.next_connection.fetch_add(1, Ordering::Relaxed);
+        clients.insert(client_id, ClientEntry::new(sender, nickname, address, connection));
+        Ok((receiver, connection))
     }
 
-    async fn unregister_client(&self, client_id: &ClientId) {
+    // With `connection`, only that connection is removed: a session ending after its client was
+    // kicked and already connected again must leave the new connection alone
+    async fn unregister_client(&self, client_id: &ClientId, connection: Option<u64>) {
         let mut clients = self.clients.lock().await;
-        clients.remove(&client_id);
+        if connection.is_some_and(|connection| clients.get(client_id).is_none_or(|entry| entry.connection != connection)) {
+            return;
+        }
+        let Some(entry) = clients.remove(client_id) else {
+            return;
+        };
+        if entry.status != UserStatus::Invisible {
+            let _ = self.broadcast.send(Message::presence(*client_id, &entry.nickname, PresenceEvent::Left));
+        }
+        drop(clients);
+        self.moderation.lock().await.forget(client_id);
+    }
+
+    fn nickname_taken(clients: &HashMap<ClientId, ClientEntry>, nickname: &str) -> bool {
+        clients.values().any(|entry| entry.nickname.eq_ignore_ascii_case(nickname))
+    }
+
+    // Mentions resolve against connected clients only
+    async fn resolve_mentions(&self, content: &str) -> Vec<ClientId> {
+        let nicknames = parse_mentions(content);
+        if nicknames.is_empty() {
+            return Vec::new();
+        }
+
+        let clients = self.clients.lock().await;
+        nicknames
+            .iter()
+            .filter_map(|nickname| {
+                clients
+                    .iter()
+                    .find(|(_, entry)| entry.nickname.eq_ignore_ascii_case(nickname))
+                    .map(|(client_id, _)| *client_id)
+            })
+            .collect()
     }
 
-    async fn handle_client_session(&self, stream: TcpStream) -> Result<()> {
-        let (client_rx, client_tx) = tokio::io::split(stream);
+    // Invisible clients are only listed to themselves
+    async fn list_clients(&self, viewer: Option<&ClientId>) -> Vec<UserInfo> {
+        self.clients
+            .lock()
+            .await
+            .iter()
+            .filter(|(id, entry)| entry.status != UserStatus::Invisible || Some(*id) == viewer)
+            .map(|(id, entry)| entry.info(*id))
+            .collect()
+    }
+
+    pub(crate) async fn room_members(&self, room: &str, viewer: &ClientId) -> Vec<UserInfo> {
+        self.clients
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, entry)| entry.room == room)
+            .filter(|(id, entry)| entry.status != UserStatus::Invisible || *id == viewer)
+            .map(|(id, entry)| entry.info(*id))
+            .collect()
+    }
+
+    pub(crate) async fn change_nickname(&self, client_id: ClientId, nickname: &str) -> Result<()> {
+        if !is_valid_nickname(nickname) {
+            bail!("Nicknames are up to {} letters, digits, '_' or '-'", MAX_NICKNAME_LEN);
+        }
+
+        let invisible = {
+            let mut clients = self.clients.lock().await;
+            let taken = clients
+                .iter()
+                .any(|(id, entry)| *id != client_id && entry.nickname.eq_ignore_ascii_case(nickname));
+            if taken {
+                bail!("The nickname {} is already taken", nickname);
+            }
+            let entry = clients.get_mut(&client_id).ok_or_else(|| anyhow!("You are not connected"))?;
+            entry.nickname = nickname.to_string();
+            entry.status == UserStatus::Invisible
+        };
+
+        let changed = Message::NicknameChanged { client_id, nickname: nickname.to_string() };
+        if invisible {
+            self.send_direct(&client_id, changed).await;
+        } else {
+            let _ = self.broadcast.send(changed);
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn join_room(&self, client_id: ClientId, name: &str) -> Result<()> {
+        let room = normalize_room_name(name)
+            .ok_or_else(|| anyhow!("Room names are letters, digits, '_' or '-'"))?;
+        let left = format!("left for #{}", room);
+        self.move_client(client_id, &room, &left).await
+    }
+
+    // Announces `left` to the previous room, unless the client is invisible
+    async fn move_client(&self, client_id: ClientId, room: &str, left: &str) -> Result<()> {
+        let (previous, nickname, invisible) = {
+            let mut clients = self.clients.lock().await;
+            let entry = clients.get_mut(&client_id).ok_or_else(|| anyhow!("You are not connected"))?;
+            if entry.room == room {
+                bail!("You are already in #{}", room);
+            }
+            let previous = std::mem::replace(&mut entry.room, room.to_string());
+            (previous, entry.nickname.clone(), entry.status == UserStatus::Invisible)
+        };
+
+        let settings = self.room(room).await;
+        if !invisible {
+            let left = Message::system(&format!("{} {}", nickname, left));
+            self.send_to_room(&previous, Some(&client_id), left).await;
+            let joined = Message::system(&format!("{} joined #{}", nickname, room));
+            self.send_to_room(room, Some(&client_id), joined).await;
+        }
+        self.send_direct(&client_id, Self::room_changed(room.to_string(), settings)).await;
+        Ok(())
+    }
+
+    async fn record_audit(&self, actor: ClientId, event: AuditEvent) {
+        if let Err(e) = self.audit.lock().await.record(actor, event) {
+            eprintln!("Failed to write audit log: {}", e);
+        }
+    }
+
+    // Moderators need `permission` in the scope and may only act on clients ranked below them
+    async fn authorize_moderation(
+        &self,
+        actor: ClientId,
+        target: ClientId,
+        room: Option<&str>,
+        permission: Permission,
+    ) -> Result<()> {
+        let actor_role = self.role_in_scope(&actor, room).await;
+        if !actor_role.can(permission) {
+            return Err(self.denied(actor, &format!("As a {} you are not allowed to do that here", actor_role)).await);
+        }
+        if actor == target {
+            bail!("You cannot do that to yourself");
+        }
+        if self.role_in_scope(&target, room).await >= actor_role {
+            return Err(self.denied(actor, "You cannot moderate someone ranked as high as you").await);
+        }
+        Ok(())
+    }
+
+    async fn actor_context(&self, actor: &ClientId) -> Result<(RoomId, String)> {
+        let clients = self.clients.lock().await;
+        let entry = clients.get(actor).ok_or_else(|| anyhow!("You are not connected"))?;
+        Ok((entry.room.clone(), entry.nickname.clone()))
+    }
+
+    async fn target_nickname(&self, target: &ClientId) -> String {
+        self.nickname(target).await.unwrap_or_else(|| default_nickname(target))
+    }
+
+    // Mutes apply to the moderator's current room
+    pub(crate) async fn mute_client(&self, actor: ClientId, target: ClientId, duration: Duration) -> Result<()> {
+        let (room, actor_name) = self.actor_context(&actor).await?;
+        self.authorize_moderation(actor, target, Some(&room), Permission::Mute).await?;
+        let duration = duration.min(MAX_MUTE);
+        if duration.is_zero() {
+            bail!("A mute needs a duration, e.g. 10m");
+        }
+
+        self.moderation.lock().await.mute(&room, target, duration);
+        self.record_audit(actor, AuditEvent::Muted { target, room: room.clone(), seconds: duration.as_secs() }).await;
+
+        let target_name = self.target_nickname(&target).await;
+        let length = format_duration(duration);
+        self.send_system(&target, &format!("{} muted you in #{} for {}", actor_name, room, length)).await;
+        let notice = Message::system(&format!("{} muted {} for {}", actor_name, target_name, length));
+        self.send_to_room(&room, Some(&target), notice).await;
+        Ok(())
+    }
+
+    pub(crate) async fn unmute_client(&self, actor: ClientId, target: ClientId) -> Result<()> {
+        let (room, actor_name) = self.actor_context(&actor).await?;
+        self.authorize_moderation(actor, target, Some(&room), Permission::Mute).await?;
+        let target_name = self.target_nickname(&target).await;
+        if !self.moderation.lock().await.unmute(&room, target) {
+            bail!("{} is not muted in #{}", target_name, room);
+        }
+        self.record_audit(actor, AuditEvent::Unmuted { target, room: room.clone() }).await;
+
+        self.send_system(&target, &format!("{} unmuted you in #{}", actor_name, room)).await;
+        let notice = Message::system(&format!("{} unmuted {}", actor_name, target_name));
+        self.send_to_room(&room, Some(&target), notice).await;
+        Ok(())
+    }
+
+    // Sends the target from the moderator's current room back to the default room
+    pub(crate) async fn kick_from_room(&self, actor: ClientId, target: ClientId, reason: Option<&str>) -> Result<()> {
+        let (room, actor_name) = self.actor_context(&actor).await?;
+        if room == DEFAULT_ROOM {
+            bail!("Nobody can be kicked from #{}, use /disconnect instead", DEFAULT_ROOM);
+        }
+        self.authorize_moderation(actor, target, Some(&room), Permission::Kick).await?;
+        if self.current_room(&target).await.as_deref() != Some(room.as_str()) {
+            bail!("{} is not in #{}", self.target_nickname(&target).await, room);
+        }
+
+        let because = reason.map(|reason| format!(": {}", reason)).unwrap_or_default();
+        self.send_system(&target, &format!("{} kicked you from #{}{}", actor_name, room, because)).await;
+        self.move_client(target, DEFAULT_ROOM, &format!("was kicked by {}{}", actor_name, because)).await?;
+        self.record_audit(actor, AuditEvent::Kicked {
+            target,
+            room: Some(room),
+            reason: reason.map(str::to_string),
+        }).await;
+        Ok(())
+    }
+
+    // Ends the target's session; with `ban` the client id, and the address it is connected from,
+    // are also refused from now on
+    pub(crate) async fn disconnect_client(&self, actor: ClientId, target: ClientId, reason: Option<&str>, ban: bool) -> Result<()> {
+        let permission = if ban { Permission::Ban } else { Permission::Kick };
+        self.authorize_moderation(actor, target, None, permission).await?;
+        let (_, actor_name) = self.actor_context(&actor).await?;
+
+        let because = reason.map(|reason| format!(": {}", reason)).unwrap_or_default();
+        let reason = reason.map(str::to_string);
+        if ban {
+            let address = self.clients.lock().await.get(&target).and_then(|entry| entry.address);
+            self.moderation.lock().await.ban(target, address)?;
+            self.send_system(&target, &format!("{} banned you from the server{}", actor_name, because)).await;
+            self.record_audit(actor, AuditEvent::Banned { target, reason }).await;
+        } else {
+            self.send_system(&target, &format!("{} disconnected you{}", actor_name, because)).await;
+            self.record_audit(actor, AuditEvent::Kicked { target, room: None, reason }).await;
+        }
+
+        // Dropping the registry entry closes the client's queue once the notice above is written
+        self.unregister_client(&target, None).await;
+        Ok(())
+    }
+
+    pub(crate) async fn unban_client(&self, actor: ClientId, target: ClientId) -> Result<()> {
+        self.require_server_permission(actor, Permission::Ban).await?;
+        if !self.moderation.lock().await.unban(&target)? {
+            bail!("{} is not banned", target);
+        }
+        self.record_audit(actor, AuditEvent::Unbanned { target }).await;
+        self.send_system(&actor, &format!("{} is no longer banned", target)).await;
+        Ok(())
+    }
+
+    // `None` turns slow mode off for the moderator's current room
+    pub(crate) async fn set_slow_mode(&self, actor: ClientId, interval: Option<Duration>) -> Result<()> {
+        let (room, actor_name) = self.actor_context(&actor).await?;
+        self.require(&actor, &room, Permission::Mute).await?;
+        let seconds = interval
+            .map(|interval| interval.min(MAX_SLOW_MODE).as_secs())
+            .filter(|seconds| *seconds > 0);
+
+        self.rooms.lock().await.update(&room, |settings| settings.slow_mode_secs = seconds)?;
+        self.record_audit(actor, AuditEvent::SlowMode { room: room.clone(), seconds }).await;
+
+        let notice = match seconds {
+            Some(seconds) => format!(
+                "{} turned on slow mode: one message every {}",
+                actor_name,
+                format_duration(Duration::from_secs(seconds))
+            ),
+            None => format!("{} turned off slow mode", actor_name),
+        };
+        self.send_to_room(&room, None, Message::system(&notice)).await;
+        Ok(())
+    }
+
+    // Moderators are exempt from slow mode. Only accepted posts start the wait, see `record_post`
+    async fn check_can_post(&self, client_id: ClientId, room: &str) -> Result<()> {
+        let role = self.role_in(&client_id, room).await;
+        let slow_mode = self.room(room).await.slow_mode_secs;
+
+        let mut moderation = self.moderation.lock().await;
+        if let Some(left) = moderation.muted_for(room, client_id) {
+            bail!("You are muted in #{} for another {}", room, format_duration(left));
+        }
+        if let Some(seconds) = slow_mode
+            && !role.can(Permission::Mute)
+            && let Some(wait) = moderation.slow_mode_wait(room, client_id, Duration::from_secs(seconds))
+        {
+            bail!("Slow mode is on in #{}, wait {} before posting again", room, format_duration(wait));
+        }
+        Ok(())
+    }
+
+    // An empty topic clears it
+    pub(crate) async fn set_topic(&self, client_id: ClientId, room: &str, topic: &str) -> Result<()> {
+        let topic: String = topic.trim().chars().take(MAX_TOPIC_LEN).collect();
+        let topic = (!topic.is_empty()).then_some(topic);
+        let notice = match &topic {
+            Some(topic) => format!("set the topic of #{} to: {}", room, topic),
+            None => format!("cleared the topic of #{}", room),
+        };
+        self.update_room(client_id, room, &notice, |settings| settings.topic = topic).await
+    }
+
+    // An empty description clears it
+    pub(crate) async fn set_description(&self, client_id: ClientId, room: &str, description: &str) -> Result<()> {
+        let description: String = description.trim().chars().take(MAX_DESCRIPTION_LEN).collect();
+        let description = (!description.is_empty()).then_some(description);
+        let notice = match description {
+            Some(_) => format!("updated the description of #{}", room),
+            None => format!("cleared the description of #{}", room),
+        };
+        self.update_room(client_id, room, &notice, |settings| settings.description = description).await
+    }
+
+    async fn update_room(&self, client_id: ClientId, room: &str, notice: &str, change: impl FnOnce(&mut Room)) -> Result<()> {
+        let nickname = self.nickname(&client_id).await.ok_or_else(|| anyhow!("You are not connected"))?;
+        let settings = self.rooms.lock().await.update(room, change)?;
+
+        let updated = Message::RoomUpdated {
+            room: room.to_string(),
+            topic: settings.topic,
+            description: settings.description,
+        };
+        self.send_to_room(room, None, updated).await;
+        self.send_to_room(room, None, Message::system(&format!("{} {}", nickname, notice))).await;
+        Ok(())
+    }
+
+    async fn run_command(&self, sending_id: ClientId, content: &str) -> Result<()> {
+        let (name, args) = parse_command(content).ok_or_else(|| anyhow!("Type /help for a list of commands"))?;
+        let command = self
+            .command(&name)
+            .await
+            .ok_or_else(|| anyhow!("Unknown command /{}, type /help for a list of commands", name))?;
+
+        if let Some(permission) = command.permission(args) {
+            let room = self.current_room(&sending_id).await.ok_or_else(|| anyhow!("You are not connected"))?;
+            self.require(&sending_id, &room, permission).await?;
+        }
+        command.execute(self, sending_id, args).await
+    }
+
+    async fn update_status(
+        &self,
+        client_id: ClientId,
+        status: UserStatus,
+        status_text: Option<String>,
+    ) {
+        let status_text = status_text
+            .map(|text| text.trim().chars().take(MAX_STATUS_TEXT_LEN).collect::<String>())
+            .filter(|text| !text.is_empty());
+
+        let (previous, nickname) = {
+            let mut clients = self.clients.lock().await;
+            let Some(entry) = clients.get_mut(&client_id) else {
+                return;
+            };
+            let previous = entry.status;
+            entry.status = status;
+            entry.status_text = status_text.clone();
+            (previous, entry.nickname.clone())
+        };
+
+        let changed = Message::StatusChanged { client_id, status, status_text };
+        match (previous, status) {
+            (UserStatus::Invisible, UserStatus::Invisible) => {}
+            (_, UserStatus::Invisible) => {
+                let _ = self.broadcast.send(Message::presence(client_id, &nickname, PresenceEvent::Left));
+            }
+            (UserStatus::Invisible, _) => {
+                let _ = self.broadcast.send(Message::presence(client_id, &nickname, PresenceEvent::Joined));
+                let _ = self.broadcast.send(changed);
+            }
+            _ => {
+                let _ = self.broadcast.send(changed);
+            }
+        }
+    }
+
+    async fn send_direct(&self, client_id: &ClientId, message: Message) {
+        let sender = self.clients.lock().await.get(client_id).map(|entry| entry.sender.clone());
+        if let Some(sender) = sender {
+            let _ = sender.send(message).await;
+        }
+    }
+
+    // Ephemeral delivery: messages are dropped for clients whose queue is full
+    async fn send_to_room(&self, room: &str, except: Option<&ClientId>, message: Message) {
+        let clients = self.clients.lock().await;
+        for (client_id, entry) in clients.iter() {
+            if Some(client_id) != except && entry.room == room {
+                let _ = entry.sender.try_send(message.clone());
+            }
+        }
+    }
+
+    async fn handle_client_session<S: Transport>(&self, stream: S, address: Option<IpAddr>) -> Result<()> {
+        let (client_rx, mut client_tx) = tokio::io::split(stream);
         let mut reader = BufReader::new(client_rx);
         let mut line_buffer = String::with_capacity(1000);
 
-        let client_id = match reader.read_line(&mut line_buffer).await {
+        let (client_id, echo, direct_rx, connection) = match reader.read_line(&mut line_buffer).await {
             Ok(0) => return Err(anyhow::anyhow!("Client disconnected during registration")),
             Ok(_) => {
                 println!("{}", line_buffer);
                 let message = line_buffer.trim();
                 let message = serde_json::from_str::<Message>(message)?;
-                if let Message::Log { client_id } = message {
-                    self.register_client(client_id).await
+                if let Message::Log { client_id, echo, nickname, token } = message {
+                    let registered = match self.admit(client_id, token.as_deref(), address).await {
+                        Ok(()) => self.register_client(client_id, nickname, address).await,
+                        Err(e) => Err(e),
+                    };
+                    match registered {
+                        Ok((direct_rx, connection)) => (client_id, echo, direct_rx, connection),
+                        Err(e) => {
+                            let source = address.map_or(AuthSource::Client(client_id), AuthSource::Address);
+                            self.record_auth_failure(source, client_id, &e.to_string()).await;
+                            let _ = Self::send_message_to_client(&mut client_tx, &Message::system(&e.to_string())).await;
+                            return Err(anyhow::anyhow!("Refused client {}: {}", client_id, e));
+                        }
+                    }
                 } else {
                     return Err(anyhow::anyhow!("Invalid message type during registration"));
                 }
@@ -64,27 +797,44 @@ impl ChatInstance {
             Err(e) => return Err(anyhow::anyhow!("Error reading from client: {}", e)),
         };
 
+        let nickname = self.target_nickname(&client_id).await;
+        self.record_audit(client_id, AuditEvent::Registered { nickname }).await;
+
+        // The first client to register an id claims it
+        match self.credentials.lock().await.issue(client_id) {
+            Ok(Some(token)) => self.send_direct(&client_id, Message::Credentials { token }).await,
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to save the token for {}: {}", client_id, e),
+        }
+
+        let motd = self.motd.lock().await.clone();
+        if let Some(motd) = motd {
+            self.send_system(&client_id, &motd).await;
+        }
+        self.send_direct(&client_id, Self::room_changed(default_room(), self.room(DEFAULT_ROOM).await)).await;
+
         // Handle incoming messages from this client
-        let incoming_task = self.spawn_message_handler(&client_id, reader);
-        let outgoing_task = self.spawn_message_routing(&client_id, client_tx);
+        let mut incoming_task = AbortOnDrop(self.spawn_message_handler(&client_id, reader));
+        let mut outgoing_task = AbortOnDrop(self.spawn_message_routing(&client_id, echo, client_tx, direct_rx));
 
         tokio::select! {
-            _ = incoming_task => {},
-            _ = outgoing_task => {},
+            _ = &mut incoming_task.0 => {},
+            _ = &mut outgoing_task.0 => {},
         }
+        drop((incoming_task, outgoing_task));
 
-        self.unregister_client(&client_id).await;
+        self.unregister_client(&client_id, Some(connection)).await;
 
         Ok(())
     }
 
-    fn spawn_message_handler(
+    fn spawn_message_handler<S: Transport>(
         &self,
         sending_id: &ClientId,
-        mut reader: BufReader<ReadHalf<TcpStream>>
+        mut reader: BufReader<ReadHalf<S>>
     ) -> tokio::task::JoinHandle<()>  {
-        let broadcast_tx = self.broadcast.clone();
-        let sending_id = sending_id.clone();
+        let chat = self.clone();
+        let sending_id = *sending_id;
 
         tokio::spawn(async move {
             let mut line_buffer = String::new();
@@ -96,12 +846,8 @@ impl ChatInstance {
                     Ok(0) => break,
                     Ok(_) => {
                         println!("Received message: {:?}", line_buffer);
-                        let message = Self::parse_message(&line_buffer).unwrap();
-                        match message {
-                            Message::Chat {client_id: _, timestamp: _, content } => {
-                                let _ = broadcast_tx.send(Message::chat(sending_id, content.as_str()));
-                            }
-                            _ => {}
+                        if let Ok(message) = Self::parse_message(&line_buffer) {
+                            chat.handle_message(sending_id, message).await;
                         }
                     }
                     Err(_) => break,
@@ -110,38 +856,379 @@ impl ChatInstance {
         })
     }
 
+    async fn handle_message(&self, sending_id: ClientId, message: Message) {
+        match message {
+            Message::Chat { content, client_msg_id, reply_to, .. } => {
+                if is_command(&content) {
+                    if let Err(e) = self.run_command(sending_id, &content).await {
+                        self.send_direct(&sending_id, Message::system(&e.to_string())).await;
+                    }
+                    return;
+                }
+                let content = content.strip_prefix('/').unwrap_or(&content);
+                if let Err(e) = self.post_chat(sending_id, content, client_msg_id, reply_to).await {
+                    // A sender waiting for its Ack is told right away why there won't be one
+                    let refusal = match client_msg_id {
+                        Some(client_msg_id) => Message::Nack { client_msg_id, reason: e.to_string() },
+                        None => Message::system(&e.to_string()),
+                    };
+                    self.send_direct(&sending_id, refusal).await;
+                }
+            }
+            query @ (Message::WhoRequest
+            | Message::ThreadRequest { .. }
+            | Message::HistoryRequest { .. }
+            | Message::RoomListRequest) => {
+                let answer = self.answer_query(sending_id, query).await;
+                self.send_direct(&sending_id, answer).await;
+            }
+            Message::Request { request_id, request } => {
+                let response = Box::new(self.answer_query(sending_id, *request).await);
+                self.send_direct(&sending_id, Message::Response { request_id, response }).await;
+            }
+            Message::SetStatus { status, status_text } => {
+                self.update_status(sending_id, status, status_text).await;
+            }
+            // The indicator goes to the sender's current room, whatever room the client named
+            Message::Typing { .. } => {
+                if let Some(room) = self.current_room(&sending_id).await {
+                    self.send_to_room(&room, Some(&sending_id), Message::typing(sending_id, &room)).await;
+                }
+            }
+            Message::ReadReceipt { message_id, .. } => {
+                self.relay_read_receipt(sending_id, message_id).await;
+            }
+            Message::Edit { message_id, new_content, .. } => {
+                if let Err(e) = self.edit_message(sending_id, message_id, &new_content).await {
+                    self.send_direct(&sending_id, Message::system(&e.to_string())).await;
+                }
+            }
+            Message::Delete { message_id, .. } => {
+                if let Err(e) = self.delete_message(sending_id, message_id).await {
+                    self.send_direct(&sending_id, Message::system(&e.to_string())).await;
+                }
+            }
+            Message::React { message_id, emoji } => {
+                if let Err(e) = self.update_reaction(sending_id, message_id, &emoji, true).await {
+                    self.send_direct(&sending_id, Message::system(&e.to_string())).await;
+                }
+            }
+            Message::Unreact { message_id, emoji } => {
+                if let Err(e) = self.update_reaction(sending_id, message_id, &emoji, false).await {
+                    self.send_direct(&sending_id, Message::system(&e.to_string())).await;
+                }
+            }
+            Message::GrantRole { client_id, role, room } => {
+                if let Err(e) = self.grant_role(sending_id, client_id, role, room.as_deref()).await {
+                    self.send_direct(&sending_id, Message::system(&e.to_string())).await;
+                }
+            }
+            Message::RevokeRole { client_id, room } => {
+                if let Err(e) = self.revoke_role(sending_id, client_id, room.as_deref()).await {
+                    self.send_direct(&sending_id, Message::system(&e.to_string())).await;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Answers go only to the asker, either directly or wrapped in a Response
+    async fn answer_query(&self, sending_id: ClientId, query: Message) -> Message {
+        match query {
+            Message::WhoRequest => Message::WhoList {
+                clients: self.list_clients(Some(&sending_id)).await,
+            },
+            // Threads are only readable from the room they were posted in
+            Message::ThreadRequest { message_id } => {
+                let room = self.current_room(&sending_id).await;
+                let history = self.history.lock().await;
+                match history.thread(message_id) {
+                    Some((root, messages)) if history.get(root).map(|stored| &stored.room) == room.as_ref() => {
+                        Message::Thread { root, messages }
+                    }
+                    Some(_) => Message::system(&format!("Message {} is not in your room", message_id)),
+                    None => Message::system(&format!("Message {} does not exist", message_id)),
+                }
+            }
+            Message::HistoryRequest { before, limit } => {
+                let Some(room) = self.current_room(&sending_id).await else {
+                    return Message::system("You are not connected");
+                };
+                let limit = limit.unwrap_or(DEFAULT_HISTORY_PAGE).clamp(1, MAX_HISTORY_PAGE);
+                let messages = self.history.lock().await.page(&room, before, limit);
+                Message::History { room, messages }
+            }
+            Message::RoomListRequest => Message::RoomList {
+                rooms: self.room_summaries(&sending_id).await,
+            },
+            _ => Message::system("Only who, thread, history and room list queries can be sent as requests"),
+        }
+    }
+
+    // Every room with visible members or settings, and always the default room
+    async fn room_summaries(&self, viewer: &ClientId) -> Vec<RoomSummary> {
+        let mut members: BTreeMap<RoomId, usize> = BTreeMap::from([(DEFAULT_ROOM.to_string(), 0)]);
+        for (id, entry) in self.clients.lock().await.iter() {
+            if entry.status != UserStatus::Invisible || id == viewer {
+                *members.entry(entry.room.clone()).or_default() += 1;
+            }
+        }
+        let rooms = self.rooms.lock().await;
+        for room in rooms.names() {
+            members.entry(room.clone()).or_default();
+        }
+        members
+            .into_iter()
+            .map(|(room, members)| RoomSummary {
+                topic: rooms.get(&room).topic,
+                room,
+                members,
+            })
+            .collect()
+    }
+
+    async fn update_reaction(&self, client_id: ClientId, message_id: MessageId, emoji: &str, add: bool) -> Result<()> {
+        let emoji = emoji.trim();
+        if emoji.is_empty() || emoji.len() > MAX_EMOJI_LEN || emoji.chars().any(char::is_whitespace) {
+            bail!("Invalid reaction");
+        }
+        let room = self.current_room(&client_id).await.ok_or_else(|| anyhow!("You are not connected"))?;
+        self.require(&client_id, &room, Permission::Post).await?;
+
+        let mut history = self.history.lock().await;
+        // Like threads, messages can only be reacted to from their own room
+        if history.get(message_id).is_some_and(|stored| stored.room != room) {
+            bail!("Message {} is not in your room", message_id);
+        }
+        let changed = if add {
+            history.react(message_id, emoji, client_id)
+        } else {
+            history.unreact(message_id, emoji, client_id)
+        };
+
+        match changed {
+            None => bail!("Message {} does not exist or was deleted", message_id),
+            Some(false) => Ok(()),
+            Some(true) => {
+                save_history(&history);
+                let _ = self.broadcast.send(Message::Reaction {
+                    message_id,
+                    emoji: emoji.to_string(),
+                    client_id,
+                    added: add,
+                    room,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) async fn post_chat(
+        &self,
+        sending_id: ClientId,
+        content: &str,
+        client_msg_id: Option<ClientMsgId>,
+        reply_to: Option<MessageId>,
+    ) -> Result<()> {
+        let room = self.current_room(&sending_id).await.ok_or_else(|| anyhow!("You are not connected"))?;
+        self.require(&sending_id, &room, Permission::Post).await?;
+        self.check_can_post(sending_id, &room).await?;
+        let content = &self.apply_filters(sending_id, &room, content, false).await?;
+        let mentions = self.resolve_mentions(content).await;
+
+        // Broadcast while holding the history lock so delivery order matches id order
+        let stored = {
+            let mut history = self.history.lock().await;
+            if let Some(parent) = reply_to
+                && history.get(parent).is_none_or(|parent| parent.deleted || parent.room != room)
+            {
+                bail!("Cannot reply to message {}: it does not exist or was deleted", parent);
+            }
+
+            let stored = history.push(sending_id, &room, content, reply_to, mentions);
+            save_history(&history);
+            let mut broadcast = stored.to_message();
+            if let Message::Chat { client_msg_id: echoed, .. } = &mut broadcast {
+                *echoed = client_msg_id;
+            }
+            let _ = self.broadcast.send(broadcast);
+            stored
+        };
+        self.moderation.lock().await.record_post(&room, sending_id);
+
+        if let Some(client_msg_id) = client_msg_id {
+            let ack = Message::Ack {
+                client_msg_id,
+                server_msg_id: stored.id,
+                timestamp: stored.timestamp,
+            };
+            self.send_direct(&sending_id, ack).await;
+        }
+
+        self.notify_mentions(sending_id, &stored).await;
+        Ok(())
+    }
+
+    async fn notify_mentions(&self, sending_id: ClientId, stored: &StoredMessage) {
+        for mentioned in stored.mentions.iter().filter(|mentioned| **mentioned != sending_id) {
+            let mention = Message::Mention {
+                message_id: stored.id,
+                from: sending_id,
+                room: stored.room.clone(),
+            };
+            self.send_direct(mentioned, mention).await;
+        }
+    }
+
+    // Authors may change their own messages, anyone else needs `permission` in the message's room
+    // The room of the message on success
+    async fn authorize_change(&self, actor: ClientId, message_id: MessageId, permission: Permission) -> Result<RoomId> {
+        let message = self.history
+            .lock()
+            .await
+            .get(message_id)
+            .filter(|stored| !stored.deleted)
+            .map(|stored| (stored.client_id, stored.room.clone()));
+
+        match message {
+            None => Err(anyhow!("Message {} does not exist or was deleted", message_id)),
+            Some((author, room)) if author == actor => Ok(room),
+            Some((_, room)) if self.role_in(&actor, &room).await.can(permission) => Ok(room),
+            Some(_) => Err(anyhow!("You can only change your own messages")),
+        }
+    }
+
+    async fn edit_message(&self, actor: ClientId, message_id: MessageId, new_content: &str) -> Result<()> {
+        let new_content = new_content.trim();
+        if new_content.is_empty() {
+            bail!("An edited message cannot be empty");
+        }
+        // Rewriting a message is posting it again, so the rules of its room apply
+        let room = self.authorize_change(actor, message_id, Permission::EditOthers).await?;
+        self.check_can_post(actor, &room).await?;
+        let new_content = &self.apply_filters(actor, &room, new_content, true).await?;
+
+        let mut history = self.history.lock().await;
+        if history.edit(message_id, new_content).is_some() {
+            save_history(&history);
+            let _ = self.broadcast.send(Message::Edit {
+                message_id,
+                new_content: new_content.to_string(),
+                room: room.clone(),
+            });
+            drop(history);
+            self.moderation.lock().await.record_post(&room, actor);
+        }
+        Ok(())
+    }
+
+    async fn delete_message(&self, actor: ClientId, message_id: MessageId) -> Result<()> {
+        self.authorize_change(actor, message_id, Permission::DeleteOthers).await?;
+
+        let deleted = {
+            let mut history = self.history.lock().await;
+            let deleted = history.delete(message_id).map(|stored| (stored.client_id, stored.room.clone()));
+            if let Some((_, room)) = &deleted {
+                save_history(&history);
+                let _ = self.broadcast.send(Message::Delete { message_id, room: room.clone() });
+            }
+            deleted
+        };
+        if let Some((author, room)) = deleted {
+            self.record_audit(actor, AuditEvent::MessageDeleted { message_id, author, room }).await;
+        }
+        Ok(())
+    }
+
+    // Receipts only go to the author of the message, and only for messages still in history and in
+    // the reader's current room
+    async fn relay_read_receipt(&self, reader_id: ClientId, message_id: MessageId) {
+        let Some(room) = self.current_room(&reader_id).await else {
+            return;
+        };
+        let author = self
+            .history
+            .lock()
+            .await
+            .get(message_id)
+            .filter(|stored| stored.room == room)
+            .map(|stored| stored.client_id);
+        if let Some(author) = author
+            && author != reader_id
+        {
+            self.send_direct(&author, Message::read_receipt(message_id, reader_id)).await;
+        }
+    }
+
     fn parse_message(encoded: &str) -> Result<Message, serde_json::Error> {
         serde_json::from_str::<Message>(encoded.trim())
     }
 
-    fn spawn_message_routing(
+    fn spawn_message_routing<S: Transport>(
         &self,
         receiving_client: &ClientId,
-        mut client_tx: WriteHalf<TcpStream>
+        echo: bool,
+        mut client_tx: WriteHalf<S>,
+        mut direct_rx: Receiver<Message>,
     ) -> tokio::task::JoinHandle<()> {
         let mut broadcast_rx = self.broadcast.subscribe();
-        let receiving_client = receiving_client.clone();
+        let receiving_client = *receiving_client;
+        let chat = self.clone();
 
         tokio::spawn(async move {
             loop {
-                if let Ok(message) = broadcast_rx.recv().await {
-                    match message {
-                        Message::Chat { content, client_id, timestamp: _ } => {
-                            if receiving_client != client_id {
-                                if Self::send_message_to_client(&mut client_tx, &Message::chat(client_id, content.as_str())).await.is_err() {
-                                    break;
-                                }
+                let message = tokio::select! {
+                    received = broadcast_rx.recv() => match received {
+                        Ok(message) => {
+                            let room = chat.current_room(&receiving_client).await.unwrap_or_else(default_room);
+                            match Self::prepare_delivery(message, &receiving_client, &room, echo) {
+                                Some(message) => message,
+                                None => continue,
                             }
                         }
-                        _ => continue
-                    }
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    direct = direct_rx.recv() => match direct {
+                        Some(message) => message,
+                        None => break,
+                    },
+                };
+
+                if Self::send_message_to_client(&mut client_tx, &message).await.is_err() {
+                    break;
                 }
             }
         })
     }
 
+    // Filters broadcast traffic for one receiver in `room`; the client_msg_id only goes back to the author
+    fn prepare_delivery(message: Message, receiving_client: &ClientId, room: &str, echo: bool) -> Option<Message> {
+        match message {
+            Message::Chat { room: ref message_room, .. }
+            | Message::Edit { room: ref message_room, .. }
+            | Message::Delete { room: ref message_room, .. }
+            | Message::Reaction { room: ref message_room, .. }
+                if message_room != room => None,
+            Message::Chat { client_id, .. } if client_id == *receiving_client => echo.then_some(message),
+            Message::Chat { content, client_id, timestamp, id, client_msg_id: _, reply_to, mentions, room } => {
+                Some(Message::Chat { content, client_id, timestamp, id, client_msg_id: None, reply_to, mentions, room })
+            }
+            Message::Presence { client_id, .. }
+            | Message::StatusChanged { client_id, .. } => {
+                (client_id != *receiving_client).then_some(message)
+            }
+            Message::Edit { .. }
+            | Message::Delete { .. }
+            | Message::Reaction { .. }
+            | Message::NicknameChanged { .. }
+            | Message::RoleChanged { .. } => Some(message),
+            _ => None,
+        }
+    }
+
     async fn send_message_to_client(
-        writer: &mut WriteHalf<TcpStream>,
+        writer: &mut (impl AsyncWrite + Unpin),
         message: &Message,
     ) -> Result<()> {
         let json = serde_json::to_string(message)?;
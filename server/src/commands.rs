@@ -0,0 +1,592 @@
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use crate::audit::AuditQuery;
+use crate::chat::{ChatInstance, ClientId};
+use crate::messages::UserStatus;
+use crate::moderation::parse_duration;
+use crate::roles::{Permission, Role};
+
+// A `/name args` command typed into the chat input
+#[async_trait]
+pub trait Command: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Shown by /help, e.g. "/nick <nickname>"
+    fn usage(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    // Checked against the sender's role in their current room before `execute`;
+    // commands that only restrict some forms can inspect the args
+    fn permission(&self, _args: &str) -> Option<Permission> {
+        None
+    }
+
+    // Errors are sent back to the sender as a System message
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()>;
+}
+
+pub struct CommandRegistry {
+    commands: BTreeMap<&'static str, Arc<dyn Command>>,
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        Self::with_builtins()
+    }
+}
+
+impl CommandRegistry {
+    pub fn empty() -> Self {
+        Self { commands: BTreeMap::new() }
+    }
+
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::empty();
+        registry.register(Help);
+        registry.register(Me);
+        registry.register(Nick);
+        registry.register(Who);
+        registry.register(Join);
+        registry.register(Topic);
+        registry.register(Description);
+        registry.register(Grant);
+        registry.register(Revoke);
+        registry.register(Mute);
+        registry.register(Unmute);
+        registry.register(Kick);
+        registry.register(Disconnect);
+        registry.register(Ban);
+        registry.register(Unban);
+        registry.register(SlowMode);
+        registry.register(Audit);
+        registry
+    }
+
+    // Replaces any command already registered under the same name
+    pub fn register(&mut self, command: impl Command + 'static) {
+        self.commands.insert(command.name(), Arc::new(command));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Command>> {
+        self.commands.get(name).cloned()
+    }
+
+    pub fn all(&self) -> Vec<Arc<dyn Command>> {
+        self.commands.values().cloned().collect()
+    }
+}
+
+// Content starting with a single '/' is a command, "//" escapes a literal slash
+pub fn is_command(content: &str) -> bool {
+    content.starts_with('/') && !content.starts_with("//")
+}
+
+// Splits "/name rest of line" into the lowercased name and the trimmed args
+pub fn parse_command(content: &str) -> Option<(String, &str)> {
+    let line = content.trim_start().strip_prefix('/')?;
+    let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    (!name.is_empty()).then(|| (name.to_ascii_lowercase(), args.trim()))
+}
+
+struct Help;
+
+#[async_trait]
+impl Command for Help {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/help [command]"
+    }
+
+    fn description(&self) -> &'static str {
+        "List commands, or explain one"
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()> {
+        let text = if args.is_empty() {
+            let lines: Vec<String> = chat
+                .commands()
+                .await
+                .iter()
+                .map(|command| format!("{} — {}", command.usage(), command.description()))
+                .collect();
+            format!("Available commands:\n{}", lines.join("\n"))
+        } else {
+            let name = args.trim_start_matches('/').to_ascii_lowercase();
+            let command = chat
+                .command(&name)
+                .await
+                .ok_or_else(|| anyhow!("Unknown command /{}", name))?;
+            format!("{} — {}", command.usage(), command.description())
+        };
+        chat.send_system(&sender, &text).await;
+        Ok(())
+    }
+}
+
+struct Me;
+
+#[async_trait]
+impl Command for Me {
+    fn name(&self) -> &'static str {
+        "me"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/me <action>"
+    }
+
+    fn description(&self) -> &'static str {
+        "Describe what you are doing"
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()> {
+        if args.is_empty() {
+            bail!("Usage: {}", self.usage());
+        }
+        let nickname = chat.nickname(&sender).await.ok_or_else(|| anyhow!("You are not connected"))?;
+        chat.post_chat(sender, &format!("* {} {}", nickname, args), None, None).await
+    }
+}
+
+struct Nick;
+
+#[async_trait]
+impl Command for Nick {
+    fn name(&self) -> &'static str {
+        "nick"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/nick <nickname>"
+    }
+
+    fn description(&self) -> &'static str {
+        "Change your nickname"
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()> {
+        if args.is_empty() {
+            bail!("Usage: {}", self.usage());
+        }
+        chat.change_nickname(sender, args).await
+    }
+}
+
+struct Who;
+
+#[async_trait]
+impl Command for Who {
+    fn name(&self) -> &'static str {
+        "who"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/who"
+    }
+
+    fn description(&self) -> &'static str {
+        "List who is in your room"
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, _args: &str) -> Result<()> {
+        let room = chat.current_room(&sender).await.ok_or_else(|| anyhow!("You are not connected"))?;
+        let mut names: Vec<String> = chat
+            .room_members(&room, &sender)
+            .await
+            .into_iter()
+            .map(|member| match member.status {
+                UserStatus::Online => member.nickname,
+                UserStatus::Away => format!("{} (away)", member.nickname),
+                UserStatus::Busy => format!("{} (busy)", member.nickname),
+                UserStatus::Invisible => format!("{} (invisible)", member.nickname),
+            })
+            .collect();
+        names.sort_by_key(|name| name.to_ascii_lowercase());
+        chat.send_system(&sender, &format!("In #{}: {}", room, names.join(", "))).await;
+        Ok(())
+    }
+}
+
+struct Join;
+
+#[async_trait]
+impl Command for Join {
+    fn name(&self) -> &'static str {
+        "join"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/join <room>"
+    }
+
+    fn description(&self) -> &'static str {
+        "Move to another room, creating it if needed"
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()> {
+        if args.is_empty() {
+            bail!("Usage: {}", self.usage());
+        }
+        chat.join_room(sender, args).await
+    }
+}
+
+struct Topic;
+
+#[async_trait]
+impl Command for Topic {
+    fn name(&self) -> &'static str {
+        "topic"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/topic [new topic | -]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Show the room topic, moderators can change or clear it"
+    }
+
+    fn permission(&self, args: &str) -> Option<Permission> {
+        (!args.is_empty()).then_some(Permission::SetTopic)
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()> {
+        let room = chat.current_room(&sender).await.ok_or_else(|| anyhow!("You are not connected"))?;
+        if !args.is_empty() {
+            return chat.set_topic(sender, &room, clearing(args)).await;
+        }
+
+        let text = match chat.room(&room).await.topic {
+            Some(topic) => format!("Topic for #{}: {}", room, topic),
+            None => format!("No topic is set for #{}", room),
+        };
+        chat.send_system(&sender, &text).await;
+        Ok(())
+    }
+}
+
+struct Description;
+
+#[async_trait]
+impl Command for Description {
+    fn name(&self) -> &'static str {
+        "description"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/description [new description | -]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Show the room description, moderators can change or clear it"
+    }
+
+    fn permission(&self, args: &str) -> Option<Permission> {
+        (!args.is_empty()).then_some(Permission::SetTopic)
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()> {
+        let room = chat.current_room(&sender).await.ok_or_else(|| anyhow!("You are not connected"))?;
+        if !args.is_empty() {
+            return chat.set_description(sender, &room, clearing(args)).await;
+        }
+
+        let text = match chat.room(&room).await.description {
+            Some(description) => format!("About #{}: {}", room, description),
+            None => format!("No description is set for #{}", room),
+        };
+        chat.send_system(&sender, &text).await;
+        Ok(())
+    }
+}
+
+struct Grant;
+
+#[async_trait]
+impl Command for Grant {
+    fn name(&self) -> &'static str {
+        "grant"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/grant <nickname> <role> [#room]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Give someone a role on the server or in one room (owners only)"
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()> {
+        let mut parts = args.split_whitespace();
+        let (Some(nickname), Some(role), room) = (parts.next(), parts.next(), parts.next()) else {
+            bail!("Usage: {}", self.usage());
+        };
+        let role: Role = role.parse()?;
+        let target = online_client(chat, nickname).await?;
+        chat.grant_role(sender, target, role, room).await
+    }
+}
+
+struct Revoke;
+
+#[async_trait]
+impl Command for Revoke {
+    fn name(&self) -> &'static str {
+        "revoke"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/revoke <nickname> [#room]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Remove someone's role on the server or in one room (owners only)"
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()> {
+        let mut parts = args.split_whitespace();
+        let (Some(nickname), room) = (parts.next(), parts.next()) else {
+            bail!("Usage: {}", self.usage());
+        };
+        let target = online_client(chat, nickname).await?;
+        chat.revoke_role(sender, target, room).await
+    }
+}
+
+struct Mute;
+
+#[async_trait]
+impl Command for Mute {
+    fn name(&self) -> &'static str {
+        "mute"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/mute <nickname> <duration>"
+    }
+
+    fn description(&self) -> &'static str {
+        "Stop someone posting in your room for a while, e.g. /mute bob 10m (moderators)"
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()> {
+        let mut parts = args.split_whitespace();
+        let (Some(nickname), Some(duration)) = (parts.next(), parts.next()) else {
+            bail!("Usage: {}", self.usage());
+        };
+        let duration = parse_duration(duration)?;
+        let target = online_client(chat, nickname).await?;
+        chat.mute_client(sender, target, duration).await
+    }
+}
+
+struct Unmute;
+
+#[async_trait]
+impl Command for Unmute {
+    fn name(&self) -> &'static str {
+        "unmute"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/unmute <nickname>"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lift a mute in your room (moderators)"
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()> {
+        if args.is_empty() {
+            bail!("Usage: {}", self.usage());
+        }
+        let target = online_client(chat, args).await?;
+        chat.unmute_client(sender, target).await
+    }
+}
+
+struct Kick;
+
+#[async_trait]
+impl Command for Kick {
+    fn name(&self) -> &'static str {
+        "kick"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/kick <nickname> [reason]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Send someone from your room back to the lobby (moderators)"
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()> {
+        let (nickname, reason) = target_and_reason(args).ok_or_else(|| anyhow!("Usage: {}", self.usage()))?;
+        let target = online_client(chat, nickname).await?;
+        chat.kick_from_room(sender, target, reason).await
+    }
+}
+
+struct Disconnect;
+
+#[async_trait]
+impl Command for Disconnect {
+    fn name(&self) -> &'static str {
+        "disconnect"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/disconnect <nickname> [reason]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Kick someone off the server (server moderators)"
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()> {
+        let (nickname, reason) = target_and_reason(args).ok_or_else(|| anyhow!("Usage: {}", self.usage()))?;
+        let target = online_client(chat, nickname).await?;
+        chat.disconnect_client(sender, target, reason, false).await
+    }
+}
+
+struct Ban;
+
+#[async_trait]
+impl Command for Ban {
+    fn name(&self) -> &'static str {
+        "ban"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/ban <nickname> [reason]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Disconnect someone and refuse them from now on (owners)"
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()> {
+        let (nickname, reason) = target_and_reason(args).ok_or_else(|| anyhow!("Usage: {}", self.usage()))?;
+        let target = online_client(chat, nickname).await?;
+        chat.disconnect_client(sender, target, reason, true).await
+    }
+}
+
+struct Unban;
+
+#[async_trait]
+impl Command for Unban {
+    fn name(&self) -> &'static str {
+        "unban"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/unban <client id>"
+    }
+
+    fn description(&self) -> &'static str {
+        "Let a banned client connect again (owners)"
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()> {
+        let target = args.parse().map_err(|_| anyhow!("Usage: {}", self.usage()))?;
+        chat.unban_client(sender, target).await
+    }
+}
+
+struct SlowMode;
+
+#[async_trait]
+impl Command for SlowMode {
+    fn name(&self) -> &'static str {
+        "slowmode"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/slowmode <interval | off>"
+    }
+
+    fn description(&self) -> &'static str {
+        "Limit everyone in your room to one message per interval, e.g. /slowmode 30s (moderators)"
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()> {
+        let interval = match args {
+            "" => bail!("Usage: {}", self.usage()),
+            "off" => None,
+            interval => Some(parse_duration(interval)?),
+        };
+        chat.set_slow_mode(sender, interval).await
+    }
+}
+
+const DEFAULT_AUDIT_LINES: usize = 10;
+
+struct Audit;
+
+#[async_trait]
+impl Command for Audit {
+    fn name(&self) -> &'static str {
+        "audit"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/audit [count | verify]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Show the latest audit log entries or check the log for tampering (owners)"
+    }
+
+    async fn execute(&self, chat: &ChatInstance, sender: ClientId, args: &str) -> Result<()> {
+        chat.require_server_permission(sender, Permission::ViewAudit).await?;
+
+        let text = if args == "verify" {
+            match chat.verify_audit_log().await {
+                Ok(count) => format!("The audit log is intact ({} entries)", count),
+                Err(e) => format!("The audit log failed verification: {}", e),
+            }
+        } else {
+            let limit = match args {
+                "" => DEFAULT_AUDIT_LINES,
+                count => count.parse().map_err(|_| anyhow!("Usage: {}", self.usage()))?,
+            };
+            let query = AuditQuery { limit: Some(limit), ..AuditQuery::default() };
+            let entries = chat.audit_entries(&query).await;
+            if entries.is_empty() {
+                "The audit log is empty".to_string()
+            } else {
+                entries.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n")
+            }
+        };
+        chat.send_system(&sender, &text).await;
+        Ok(())
+    }
+}
+
+async fn online_client(chat: &ChatInstance, nickname: &str) -> Result<ClientId> {
+    chat.client_by_nickname(nickname)
+        .await
+        .ok_or_else(|| anyhow!("Nobody called {} is online", nickname))
+}
+
+// Splits "nickname some reason" into the nickname and an optional reason
+fn target_and_reason(args: &str) -> Option<(&str, Option<&str>)> {
+    let (nickname, reason) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let reason = reason.trim();
+    (!nickname.is_empty()).then_some((nickname, (!reason.is_empty()).then_some(reason)))
+}
+
+// A lone "-" clears a room setting
+fn clearing(args: &str) -> &str {
+    if args == "-" { "" } else { args }
+}
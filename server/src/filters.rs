@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::chat::ClientId;
+
+pub const DEFAULT_MAX_MESSAGE_LEN: usize = 2000;
+
+pub enum FilterVerdict {
+    Allow,
+    // Continue with the rewritten content
+    Modify(String),
+    // Drop the message, the reason is sent back to the sender
+    Reject(String),
+}
+
+pub struct FilterContext<'a> {
+    pub sender: ClientId,
+    pub room: &'a str,
+    // Rewriting an existing message rather than posting a new one
+    pub edit: bool,
+}
+
+// Runs on every chat message and edit before it is stored or broadcast
+pub trait MessageFilter: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn check(&self, context: &FilterContext, content: &str) -> FilterVerdict;
+
+    // Called once every filter let the message through, with its final content; filters that keep
+    // state about past messages record them here so refused ones don't count
+    fn accepted(&self, _context: &FilterContext, _content: &str) {}
+}
+
+// Filters run in insertion order, each one sees the content as left by the previous ones
+#[derive(Default)]
+pub struct FilterPipeline {
+    filters: Vec<Box<dyn MessageFilter>>,
+}
+
+impl FilterPipeline {
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    pub fn with_defaults() -> Self {
+        let mut pipeline = Self::empty();
+        pipeline.push(MaxLength::new(DEFAULT_MAX_MESSAGE_LEN));
+        pipeline.push(RepeatSpam::new(3, Duration::from_secs(30)));
+        pipeline
+    }
+
+    pub fn push(&mut self, filter: impl MessageFilter + 'static) {
+        self.filters.push(Box::new(filter));
+    }
+
+    // The content to post, or the reason it was rejected
+    pub fn run(&self, context: &FilterContext, content: &str) -> Result<String, String> {
+        let mut content = content.to_string();
+        for filter in &self.filters {
+            match filter.check(context, &content) {
+                FilterVerdict::Allow => {}
+                FilterVerdict::Modify(modified) => content = modified,
+                FilterVerdict::Reject(reason) => return Err(reason),
+            }
+        }
+        if content.trim().is_empty() {
+            return Err("Your message is empty".to_string());
+        }
+        for filter in &self.filters {
+            filter.accepted(context, &content);
+        }
+        Ok(content)
+    }
+}
+
+// Replaces blocked words, matched case-insensitively as whole words, with asterisks
+pub struct WordBlocklist {
+    words: Vec<String>,
+}
+
+impl WordBlocklist {
+    pub fn new<I, S>(words: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let words = words
+            .into_iter()
+            .map(|word| word.as_ref().trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+        Self { words }
+    }
+}
+
+impl MessageFilter for WordBlocklist {
+    fn name(&self) -> &'static str {
+        "word-blocklist"
+    }
+
+    fn check(&self, _context: &FilterContext, content: &str) -> FilterVerdict {
+        let mut masked = String::with_capacity(content.len());
+        let mut changed = false;
+        let mut word = String::new();
+
+        let mut flush = |word: &mut String, masked: &mut String| {
+            if self.words.contains(&word.to_lowercase()) {
+                masked.extend(word.chars().map(|_| '*'));
+                changed = true;
+            } else {
+                masked.push_str(word);
+            }
+            word.clear();
+        };
+
+        for c in content.chars() {
+            if c.is_alphanumeric() || c == '\'' {
+                word.push(c);
+            } else {
+                flush(&mut word, &mut masked);
+                masked.push(c);
+            }
+        }
+        flush(&mut word, &mut masked);
+
+        if changed {
+            FilterVerdict::Modify(masked)
+        } else {
+            FilterVerdict::Allow
+        }
+    }
+}
+
+pub struct LinkBlocker;
+
+impl MessageFilter for LinkBlocker {
+    fn name(&self) -> &'static str {
+        "link-blocker"
+    }
+
+    fn check(&self, _context: &FilterContext, content: &str) -> FilterVerdict {
+        let has_link = content.split_whitespace().any(|token| {
+            let token = token.to_ascii_lowercase();
+            token.contains("http://") || token.contains("https://") || token.starts_with("www.")
+        });
+        if has_link {
+            FilterVerdict::Reject("Links are not allowed here".to_string())
+        } else {
+            FilterVerdict::Allow
+        }
+    }
+}
+
+// Cuts messages down to `max_chars` characters, marking the cut with an ellipsis
+pub struct MaxLength {
+    max_chars: usize,
+}
+
+impl MaxLength {
+    pub fn new(max_chars: usize) -> Self {
+        Self { max_chars: max_chars.max(1) }
+    }
+}
+
+impl MessageFilter for MaxLength {
+    fn name(&self) -> &'static str {
+        "max-length"
+    }
+
+    fn check(&self, _context: &FilterContext, content: &str) -> FilterVerdict {
+        if content.chars().count() <= self.max_chars {
+            return FilterVerdict::Allow;
+        }
+        let mut truncated: String = content.chars().take(self.max_chars - 1).collect();
+        truncated.push('…');
+        FilterVerdict::Modify(truncated)
+    }
+}
+
+struct LastMessage {
+    content: String,
+    repeats: usize,
+    first_sent: Instant,
+}
+
+// Rejects a sender's message once it repeated the same text `max_repeats` times within `window`.
+// Edits are left alone
+pub struct RepeatSpam {
+    max_repeats: usize,
+    window: Duration,
+    last: Mutex<HashMap<ClientId, LastMessage>>,
+}
+
+impl RepeatSpam {
+    pub fn new(max_repeats: usize, window: Duration) -> Self {
+        Self {
+            max_repeats: max_repeats.max(1),
+            window,
+            last: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl MessageFilter for RepeatSpam {
+    fn name(&self) -> &'static str {
+        "repeat-spam"
+    }
+
+    fn check(&self, context: &FilterContext, content: &str) -> FilterVerdict {
+        if context.edit {
+            return FilterVerdict::Allow;
+        }
+        let normalized = content.trim().to_lowercase();
+        let now = Instant::now();
+        let mut last = self.last.lock().unwrap();
+        // Runs outside the window start over anyway, dropping them keeps one entry per recent sender
+        last.retain(|_, previous| now.duration_since(previous.first_sent) < self.window);
+
+        match last.get(&context.sender) {
+            Some(previous) if previous.content == normalized && previous.repeats >= self.max_repeats => {
+                FilterVerdict::Reject("Please don't send the same message over and over".to_string())
+            }
+            _ => FilterVerdict::Allow,
+        }
+    }
+
+    fn accepted(&self, context: &FilterContext, content: &str) {
+        if context.edit {
+            return;
+        }
+        let normalized = content.trim().to_lowercase();
+        let mut last = self.last.lock().unwrap();
+        match last.get_mut(&context.sender) {
+            Some(previous) if previous.content == normalized => previous.repeats += 1,
+            _ => {
+                last.insert(context.sender, LastMessage {
+                    content: normalized,
+                    repeats: 1,
+                    first_sent: Instant::now(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn context(sender: ClientId) -> FilterContext<'static> {
+        FilterContext { sender, room: "general", edit: false }
+    }
+
+    fn verdict(filter: &dyn MessageFilter, content: &str) -> Result<Option<String>, String> {
+        match filter.check(&context(ClientId::nil()), content) {
+            FilterVerdict::Allow => Ok(None),
+            FilterVerdict::Modify(modified) => Ok(Some(modified)),
+            FilterVerdict::Reject(reason) => Err(reason),
+        }
+    }
+
+    #[test]
+    fn word_blocklist_masks_whole_words_in_any_case() {
+        let filter = WordBlocklist::new([" Darn ", ""]);
+        assert_eq!(verdict(&filter, "darn it, DARN!"), Ok(Some("**** it, ****!".to_string())));
+        assert_eq!(verdict(&filter, "darned dam"), Ok(None));
+    }
+
+    #[test]
+    fn link_blocker_rejects_urls_only() {
+        assert!(verdict(&LinkBlocker, "see HTTPS://example.com").is_err());
+        assert!(verdict(&LinkBlocker, "www.example.com").is_err());
+        assert_eq!(verdict(&LinkBlocker, "meet at the www"), Ok(None));
+    }
+
+    #[test]
+    fn max_length_counts_characters_and_marks_the_cut() {
+        let filter = MaxLength::new(4);
+        assert_eq!(verdict(&filter, "ünïc"), Ok(None));
+        assert_eq!(verdict(&filter, "ünïcode"), Ok(Some("ünï…".to_string())));
+    }
+
+    #[test]
+    fn repeat_spam_rejects_repeats_within_the_window_per_sender() {
+        let filter = RepeatSpam::new(2, Duration::from_secs(30));
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let check = |sender, content| {
+            let rejected = matches!(filter.check(&context(sender), content), FilterVerdict::Reject(_));
+            if !rejected {
+                filter.accepted(&context(sender), content);
+            }
+            rejected
+        };
+
+        assert!(!check(alice, "buy now"));
+        assert!(!check(alice, " BUY NOW "));
+        assert!(check(alice, "buy now"));
+        assert!(!check(bob, "buy now"));
+        assert!(!check(alice, "something else"));
+    }
+
+    #[test]
+    fn repeat_spam_forgets_senders_once_their_window_passed() {
+        let filter = RepeatSpam::new(1, Duration::from_millis(20));
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        filter.accepted(&context(alice), "hello");
+        std::thread::sleep(Duration::from_millis(30));
+
+        assert!(matches!(filter.check(&context(bob), "hi"), FilterVerdict::Allow));
+        assert!(filter.last.lock().unwrap().is_empty());
+        assert!(matches!(filter.check(&context(alice), "hello"), FilterVerdict::Allow));
+    }
+
+    #[test]
+    fn repeat_spam_ignores_edits_and_refused_messages() {
+        let mut pipeline = FilterPipeline::empty();
+        pipeline.push(RepeatSpam::new(1, Duration::from_secs(30)));
+        pipeline.push(LinkBlocker);
+        let alice = context(Uuid::new_v4());
+        let edit = FilterContext { edit: true, ..context(alice.sender) };
+
+        assert_eq!(pipeline.run(&alice, "typo"), Ok("typo".to_string()));
+        assert_eq!(pipeline.run(&edit, "typo"), Ok("typo".to_string()));
+        assert_eq!(pipeline.run(&alice, "www.example.com"), Err("Links are not allowed here".to_string()));
+        assert_eq!(pipeline.run(&alice, "www.example.com"), Err("Links are not allowed here".to_string()));
+        assert!(pipeline.run(&alice, "typo").unwrap_err().contains("same message"));
+    }
+
+    #[test]
+    fn pipeline_stops_at_the_first_rejection_and_refuses_empty_results() {
+        let mut pipeline = FilterPipeline::empty();
+        pipeline.push(WordBlocklist::new(["spam"]));
+        pipeline.push(LinkBlocker);
+        assert_eq!(pipeline.run(&context(ClientId::nil()), "no spam"), Ok("no ****".to_string()));
+        assert!(pipeline.run(&context(ClientId::nil()), "spam www.spam.com").is_err());
+        assert!(pipeline.run(&context(ClientId::nil()), "   ").is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::chat::{ClientId, RoomId, default_room};
+use crate::messages::{Message, MessageId};
+use crate::storage::{load_json, save_json};
+
+pub const DEFAULT_HISTORY_CAPACITY: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredMessage {
+    pub id: MessageId,
+    pub client_id: ClientId,
+    pub content: String,
+    pub timestamp: i64,
+    #[serde(default = "default_room")]
+    pub room: RoomId,
+    #[serde(default)]
+    pub reply_to: Option<MessageId>,
+    #[serde(default)]
+    pub mentions: Vec<ClientId>,
+    #[serde(default)]
+    pub edited_at: Option<i64>,
+    #[serde(default)]
+    pub deleted: bool,
+    // Emoji to the clients that reacted with it, in reaction order
+    #[serde(default)]
+    pub reactions: BTreeMap<String, Vec<ClientId>>,
+}
+
+impl StoredMessage {
+    pub fn to_message(&self) -> Message {
+        Message::Chat {
+            content: self.content.clone(),
+            client_id: self.client_id,
+            timestamp: self.timestamp,
+            id: Some(self.id),
+            client_msg_id: None,
+            reply_to: self.reply_to,
+            mentions: self.mentions.clone(),
+            room: self.room.clone(),
+        }
+    }
+}
+
+// Bounded log of accepted chat messages, oldest entries are evicted first.
+// When backed by a file, `save` writes it out with edits, deletions and reactions included
+pub struct History {
+    messages: VecDeque<StoredMessage>,
+    next_id: MessageId,
+    capacity: usize,
+    path: Option<PathBuf>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_CAPACITY)
+    }
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            messages: VecDeque::with_capacity(capacity),
+            next_id: 1,
+            capacity,
+            path: None,
+        }
+    }
+
+    // Ids carry on after the last saved message
+    pub fn load(path: PathBuf, capacity: usize) -> Result<Self> {
+        let mut history = Self::new(capacity);
+        let mut messages: VecDeque<StoredMessage> = load_json(&path)?;
+        if messages.len() > history.capacity {
+            messages.drain(..messages.len() - history.capacity);
+        }
+        history.next_id = messages.back().map_or(1, |last| last.id + 1);
+        history.messages = messages;
+        history.path = Some(path);
+        Ok(history)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => save_json(path, &self.messages),
+            None => Ok(()),
+        }
+    }
+
+    pub fn push(
+        &mut self,
+        client_id: ClientId,
+        room: &str,
+        content: &str,
+        reply_to: Option<MessageId>,
+        mentions: Vec<ClientId>,
+    ) -> StoredMessage {
+        let stored = StoredMessage {
+            id: self.next_id,
+            client_id,
+            content: content.to_string(),
+            timestamp: Message::timestamp(),
+            room: room.to_string(),
+            reply_to,
+            mentions,
+            edited_at: None,
+            deleted: false,
+            reactions: BTreeMap::new(),
+        };
+        self.next_id += 1;
+
+        if self.messages.len() == self.capacity {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(stored.clone());
+        stored
+    }
+
+    // Ids are assigned in increasing order, so the deque stays sorted by id
+    pub fn get(&self, id: MessageId) -> Option<&StoredMessage> {
+        self.messages
+            .binary_search_by_key(&id, |message| message.id)
+            .ok()
+            .map(|index| &self.messages[index])
+    }
+
+    pub fn get_mut(&mut self, id: MessageId) -> Option<&mut StoredMessage> {
+        self.messages
+            .binary_search_by_key(&id, |message| message.id)
+            .ok()
+            .map(|index| &mut self.messages[index])
+    }
+
+    pub fn edit(&mut self, id: MessageId, new_content: &str) -> Option<&StoredMessage> {
+        let stored = self.get_mut(id).filter(|stored| !stored.deleted)?;
+        stored.content = new_content.to_string();
+        stored.edited_at = Some(Message::timestamp());
+        Some(stored)
+    }
+
+    // Deleted messages stay in history as tombstones so ids keep resolving
+    pub fn delete(&mut self, id: MessageId) -> Option<&StoredMessage> {
+        let stored = self.get_mut(id).filter(|stored| !stored.deleted)?;
+        stored.content.clear();
+        stored.reactions.clear();
+        stored.deleted = true;
+        Some(stored)
+    }
+
+    // Returns true if the reaction was added, false if it was already there
+    pub fn react(&mut self, id: MessageId, emoji: &str, client_id: ClientId) -> Option<bool> {
+        let stored = self.get_mut(id).filter(|stored| !stored.deleted)?;
+        let reactors = stored.reactions.entry(emoji.to_string()).or_default();
+        if reactors.contains(&client_id) {
+            return Some(false);
+        }
+        reactors.push(client_id);
+        Some(true)
+    }
+
+    // Returns true if the reaction was removed, false if there was none
+    pub fn unreact(&mut self, id: MessageId, emoji: &str, client_id: ClientId) -> Option<bool> {
+        let stored = self.get_mut(id).filter(|stored| !stored.deleted)?;
+        let Some(reactors) = stored.reactions.get_mut(emoji) else {
+            return Some(false);
+        };
+        let before = reactors.len();
+        reactors.retain(|reactor| *reactor != client_id);
+        let removed = reactors.len() != before;
+        if reactors.is_empty() {
+            stored.reactions.remove(emoji);
+        }
+        Some(removed)
+    }
+
+    // Follows reply_to links upwards as far as history still holds the parents
+    pub fn thread_root(&self, id: MessageId) -> Option<MessageId> {
+        let mut current = self.get(id)?;
+        while let Some(parent) = current.reply_to.and_then(|parent| self.get(parent)) {
+            current = parent;
+        }
+        Some(current.id)
+    }
+
+    // The root of the thread containing `id` followed by every reply below it, oldest first
+    pub fn thread(&self, id: MessageId) -> Option<(MessageId, Vec<StoredMessage>)> {
+        let root = self.thread_root(id)?;
+        let mut in_thread = HashSet::from([root]);
+        let mut thread = Vec::new();
+
+        // Replies always come after their parent, so one pass in id order is enough
+        for message in self.messages.iter().filter(|message| message.id >= root) {
+            if message.id == root || message.reply_to.is_some_and(|parent| in_thread.contains(&parent)) {
+                in_thread.insert(message.id);
+                thread.push(message.clone());
+            }
+        }
+        Some((root, thread))
+    }
+
+    // Up to `limit` messages of `room` older than `before` (or the latest ones), oldest first
+    pub fn page(&self, room: &str, before: Option<MessageId>, limit: usize) -> Vec<StoredMessage> {
+        let mut page: Vec<StoredMessage> = self
+            .messages
+            .iter()
+            .rev()
+            .filter(|message| message.room == room && before.is_none_or(|before| message.id < before))
+            .take(limit)
+            .cloned()
+            .collect();
+        page.reverse();
+        page
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
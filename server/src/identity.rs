@@ -0,0 +1,80 @@
+use anyhow::{Result, bail};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use uuid::Uuid;
+use crate::chat::ClientId;
+use crate::storage::{load_json, save_json};
+
+// Secret tokens that prove a client owns its id, so roles and bans keyed by the id can't be claimed
+// by anyone who learns it. The first client to register an id is issued its token (unless the operator
+// issued one ahead of time); every later Log for that id has to present it.
+// Only hashes are kept, saved as JSON after every change when backed by a file
+pub struct Credentials {
+    hashes: HashMap<ClientId, String>,
+    path: Option<PathBuf>,
+}
+
+impl Default for Credentials {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+impl Credentials {
+    pub fn in_memory() -> Self {
+        Self {
+            hashes: HashMap::new(),
+            path: None,
+        }
+    }
+
+    pub fn load(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            hashes: load_json(&path)?,
+            path: Some(path),
+        })
+    }
+
+    pub fn is_claimed(&self, client_id: &ClientId) -> bool {
+        self.hashes.contains_key(client_id)
+    }
+
+    // Ids nobody has claimed yet pass with any token
+    pub fn verify(&self, client_id: &ClientId, token: Option<&str>) -> Result<()> {
+        let Some(expected) = self.hashes.get(client_id) else {
+            return Ok(());
+        };
+        match token {
+            Some(token) if hash(token) == *expected => Ok(()),
+            Some(_) => bail!("Invalid token for client {}", client_id),
+            None => bail!("Client {} is claimed, connect with its token", client_id),
+        }
+    }
+
+    // A new token for an unclaimed id, None if the id already has one
+    pub fn issue(&mut self, client_id: ClientId) -> Result<Option<String>> {
+        if self.is_claimed(&client_id) {
+            return Ok(None);
+        }
+        let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        self.hashes.insert(client_id, hash(&token));
+        // A token that was never saved must not lock the id after a restart, nor now
+        if let Err(e) = self.save() {
+            self.hashes.remove(&client_id);
+            return Err(e);
+        }
+        Ok(Some(token))
+    }
+
+    fn save(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => save_json(path, &self.hashes),
+            None => Ok(()),
+        }
+    }
+}
+
+fn hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
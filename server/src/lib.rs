@@ -1,4 +1,19 @@
 pub mod messages;
 pub mod chat;
-pub use messages::Message;
-pub use chat::ClientId;
+pub mod history;
+pub mod mentions;
+pub mod rooms;
+pub mod commands;
+pub mod roles;
+pub mod storage;
+pub mod moderation;
+pub mod audit;
+pub mod filters;
+pub mod identity;
+pub use messages::{ClientMsgId, Message, MessageId, PresenceEvent, RequestId, RoomSummary, UserInfo, UserStatus};
+pub use chat::{ClientId, RoomId, Transport, DEFAULT_ROOM};
+pub use history::StoredMessage;
+pub use commands::{Command, CommandRegistry};
+pub use roles::{Permission, Role};
+pub use audit::{AuditEntry, AuditEvent, AuditQuery};
+pub use filters::{FilterContext, FilterPipeline, FilterVerdict, MessageFilter};
@@ -1,14 +1,42 @@
 use anyhow::Result;
 use std::sync::Arc;
 use tokio::net::TcpListener;
-use crate::chat::ChatInstance;
-
-mod messages;
-mod chat;
+use server::Role;
+use server::filters::{LinkBlocker, WordBlocklist};
+use server::chat::ChatInstance;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let chat = Arc::new(ChatInstance::new());
+    let data_dir = std::env::var("CHAT_DATA_DIR").unwrap_or_else(|_| "data".to_string());
+    let chat = Arc::new(ChatInstance::with_data_dir(&data_dir)?);
+    if let Ok(motd) = std::env::var("CHAT_MOTD") {
+        chat.set_motd(Some(motd)).await;
+    }
+    // Comma-separated words to mask in chat messages
+    if let Ok(words) = std::env::var("CHAT_BLOCKED_WORDS") {
+        chat.add_filter(WordBlocklist::new(words.split(','))).await;
+    }
+    if std::env::var("CHAT_BLOCK_LINKS").is_ok_and(|value| value == "1") {
+        chat.add_filter(LinkBlocker).await;
+    }
+
+    // Comma-separated client ids that own the server
+    if let Ok(owners) = std::env::var("CHAT_OWNERS") {
+        for owner in owners.split(',').map(str::trim).filter(|owner| !owner.is_empty()) {
+            let owner = owner.parse()?;
+            chat.assign_role(owner, None, Role::Owner).await?;
+            // Owners need their token before they first connect, or whoever connects first gets it
+            if let Some(token) = chat.issue_credential(owner).await? {
+                println!("Credential for owner {}: {}", owner, token);
+            }
+        }
+    }
+    // Local clients can also connect over a Unix domain socket at this path
+    #[cfg(unix)]
+    if let Ok(path) = std::env::var("CHAT_UNIX_SOCKET") {
+        listen_unix(Arc::clone(&chat), &path)?;
+    }
+
     let listener = TcpListener::bind("127.0.0.1:8080").await?;
 
     println!("Chat server listening on 127.0.0.1:8080");
@@ -19,7 +47,7 @@ async fn main() -> Result<()> {
                 println!("New client connected from: {}", addr);
                 let chat = Arc::clone(&chat);
                 tokio::spawn(async move {
-                    if let Err(e) = chat.handle_connection(stream).await {
+                    if let Err(e) = chat.handle_connection_from(stream, addr.ip()).await {
                         eprintln!("Error handling client {}: {}", addr, e);
                     }
                 });
@@ -30,3 +58,42 @@ async fn main() -> Result<()> {
         }
     }
 }
+
+#[cfg(unix)]
+fn listen_unix(chat: Arc<ChatInstance>, path: &str) -> Result<()> {
+    use anyhow::bail;
+    use std::os::unix::fs::FileTypeExt;
+
+    // A socket file left behind by a previous run would make bind fail; only one nobody answers on is removed
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.file_type().is_socket() => bail!("{} exists and is not a socket", path),
+        Ok(_) if std::os::unix::net::UnixStream::connect(path).is_ok() => {
+            bail!("Another server is already listening on {}", path)
+        }
+        Ok(_) => std::fs::remove_file(path)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    println!("Chat server listening on {}", path);
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    println!("New client connected over the Unix socket");
+                    let chat = Arc::clone(&chat);
+                    tokio::spawn(async move {
+                        if let Err(e) = chat.handle_connection(stream).await {
+                            eprintln!("Error handling Unix socket client: {}", e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    eprintln!("Error accepting connection: {}", e);
+                }
+            }
+        }
+    });
+    Ok(())
+}
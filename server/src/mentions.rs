@@ -0,0 +1,48 @@
+use crate::chat::ClientId;
+
+pub const MAX_NICKNAME_LEN: usize = 24;
+
+fn is_nickname_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+// Nicknames are 1 to MAX_NICKNAME_LEN ASCII letters, digits, '_' or '-'
+pub fn is_valid_nickname(nickname: &str) -> bool {
+    !nickname.is_empty() && nickname.len() <= MAX_NICKNAME_LEN && nickname.chars().all(is_nickname_char)
+}
+
+pub fn default_nickname(client_id: &ClientId) -> String {
+    client_id.simple().to_string()[..8].to_string()
+}
+
+// Distinct `@nickname` tokens in order of appearance, lowercased for case-insensitive lookup.
+// An '@' preceded by a nickname character (as in an email address) does not start a mention.
+pub fn parse_mentions(content: &str) -> Vec<String> {
+    let mut mentions: Vec<String> = Vec::new();
+    let mut previous = None;
+    let mut chars = content.char_indices().peekable();
+
+    while let Some((index, c)) = chars.next() {
+        if c == '@' && !previous.is_some_and(is_nickname_char) {
+            let start = index + c.len_utf8();
+            let mut end = start;
+            while let Some(&(next_index, next)) = chars.peek() {
+                if !is_nickname_char(next) {
+                    break;
+                }
+                end = next_index + next.len_utf8();
+                previous = Some(next);
+                chars.next();
+            }
+
+            let nickname = content[start..end].to_lowercase();
+            if is_valid_nickname(&nickname) && !mentions.contains(&nickname) {
+                mentions.push(nickname);
+            }
+            continue;
+        }
+        previous = Some(c);
+    }
+
+    mentions
+}
@@ -1,49 +1,376 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 use serde::{Deserialize, Serialize};
-use crate::chat::ClientId;
+use crate::chat::{ClientId, RoomId, default_room};
+use crate::history::StoredMessage;
+use crate::roles::Role;
+
+// Assigned by the server to every accepted chat message
+pub type MessageId = u64;
+// Chosen by the sending client to correlate its message with the server's Ack
+pub type ClientMsgId = u64;
+// Chosen by the sending client to correlate a Request with its Response
+pub type RequestId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PresenceEvent {
+    Joined,
+    Left,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UserStatus {
+    #[default]
+    Online,
+    Away,
+    Busy,
+    Invisible,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserInfo {
+    pub client_id: ClientId,
+    pub nickname: String,
+    pub status: UserStatus,
+    pub status_text: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomSummary {
+    pub room: RoomId,
+    pub topic: Option<String>,
+    // Visible members currently in the room
+    pub members: usize,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Message {
     Log {
         client_id: ClientId,
+        // Deliver this client's own chat messages back to it, carrying its client_msg_id
+        #[serde(default)]
+        echo: bool,
+        // Requested nickname, the server falls back to a short form of the id if invalid or taken
+        #[serde(default)]
+        nickname: Option<String>,
+        // The token the server issued for this id, required once the id is claimed
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+    },
+    // Sent once, right after the first registration of an id: keep it to connect as this id again
+    Credentials {
+        token: String,
     },
     Chat {
         content: String,
         client_id: ClientId,
         timestamp: i64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<MessageId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_msg_id: Option<ClientMsgId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reply_to: Option<MessageId>,
+        // Resolved by the server from `@nickname` tokens in the content
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        mentions: Vec<ClientId>,
+        // Set by the server to the sender's current room
+        #[serde(default = "default_room")]
+        room: RoomId,
     },
     Heartbeat,
     System {
         content: String,
     },
+    Presence {
+        client_id: ClientId,
+        nickname: String,
+        event: PresenceEvent,
+        timestamp: i64,
+    },
+    WhoRequest,
+    WhoList {
+        clients: Vec<UserInfo>,
+    },
+    SetStatus {
+        status: UserStatus,
+        status_text: Option<String>,
+    },
+    StatusChanged {
+        client_id: ClientId,
+        status: UserStatus,
+        status_text: Option<String>,
+    },
+    Typing {
+        client_id: ClientId,
+        room: RoomId,
+    },
+    Ack {
+        client_msg_id: ClientMsgId,
+        server_msg_id: MessageId,
+        timestamp: i64,
+    },
+    // The chat message was not posted, e.g. a filter or slow mode refused it
+    Nack {
+        client_msg_id: ClientMsgId,
+        reason: String,
+    },
+    ReadReceipt {
+        message_id: MessageId,
+        client_id: ClientId,
+        timestamp: i64,
+    },
+    Edit {
+        message_id: MessageId,
+        new_content: String,
+        // Set by the server to the room of the message, only its members receive the change
+        #[serde(default = "default_room")]
+        room: RoomId,
+    },
+    Delete {
+        message_id: MessageId,
+        #[serde(default = "default_room")]
+        room: RoomId,
+    },
+    ThreadRequest {
+        message_id: MessageId,
+    },
+    Thread {
+        root: MessageId,
+        messages: Vec<StoredMessage>,
+    },
+    React {
+        message_id: MessageId,
+        emoji: String,
+    },
+    Unreact {
+        message_id: MessageId,
+        emoji: String,
+    },
+    // Broadcast delta after a React or Unreact changed a message's reactions
+    Reaction {
+        message_id: MessageId,
+        emoji: String,
+        client_id: ClientId,
+        added: bool,
+        #[serde(default = "default_room")]
+        room: RoomId,
+    },
+    // Sent to each mentioned client, wherever they are
+    Mention {
+        message_id: MessageId,
+        from: ClientId,
+        room: RoomId,
+    },
+    NicknameChanged {
+        client_id: ClientId,
+        nickname: String,
+    },
+    // Names the client's current room, sent on connect and after every /join
+    RoomChanged {
+        room: RoomId,
+        topic: Option<String>,
+        description: Option<String>,
+    },
+    // Sent to everyone in a room after its topic or description changed
+    RoomUpdated {
+        room: RoomId,
+        topic: Option<String>,
+        description: Option<String>,
+    },
+    // `room: None` targets the server-wide role
+    GrantRole {
+        client_id: ClientId,
+        role: Role,
+        #[serde(default)]
+        room: Option<RoomId>,
+    },
+    RevokeRole {
+        client_id: ClientId,
+        #[serde(default)]
+        room: Option<RoomId>,
+    },
+    // Broadcast after an assignment changed, `role: None` means it was revoked
+    RoleChanged {
+        client_id: ClientId,
+        role: Option<Role>,
+        room: Option<RoomId>,
+    },
+    // Messages of the sender's current room, oldest first: the latest `limit`,
+    // or the `limit` before message `before` when paging backwards
+    HistoryRequest {
+        #[serde(default)]
+        before: Option<MessageId>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    History {
+        room: RoomId,
+        messages: Vec<StoredMessage>,
+    },
+    RoomListRequest,
+    RoomList {
+        rooms: Vec<RoomSummary>,
+    },
+    // Wraps a query (WhoRequest, ThreadRequest, HistoryRequest or RoomListRequest)
+    // so the answer comes back in a Response with the same id
+    Request {
+        request_id: RequestId,
+        request: Box<Message>,
+    },
+    // The answer, or a System message explaining why there is none
+    Response {
+        request_id: RequestId,
+        response: Box<Message>,
+    },
 }
 
 impl Message {
-    #[allow(dead_code)]
     pub fn system(content: &str) -> Self {
         Self::System {
             content: content.to_string(),
         }
     }
 
-    #[allow(dead_code)]
-    pub fn log(client_id: ClientId) -> Self {
-        Self::Log { client_id }
+    pub fn log(client_id: ClientId, echo: bool, nickname: Option<&str>, token: Option<&str>) -> Self {
+        Self::Log {
+            client_id,
+            echo,
+            nickname: nickname.map(str::to_string),
+            token: token.map(str::to_string),
+        }
     }
 
     pub fn chat(client_id: ClientId, content: &str) -> Self {
         Self::Chat {
             content: content.to_string(),
-            client_id: client_id,
+            client_id,
+            timestamp: Self::timestamp(),
+            id: None,
+            client_msg_id: None,
+            reply_to: None,
+            mentions: Vec::new(),
+            room: default_room(),
+        }
+    }
+
+    // Without a `client_msg_id` the server sends no Ack
+    pub fn outgoing_chat(
+        client_id: ClientId,
+        content: &str,
+        client_msg_id: Option<ClientMsgId>,
+        reply_to: Option<MessageId>,
+    ) -> Self {
+        Self::Chat {
+            content: content.to_string(),
+            client_id,
+            timestamp: Self::timestamp(),
+            id: None,
+            client_msg_id,
+            reply_to,
+            mentions: Vec::new(),
+            room: default_room(),
+        }
+    }
+
+    pub fn read_receipt(message_id: MessageId, client_id: ClientId) -> Self {
+        Self::ReadReceipt {
+            message_id,
+            client_id,
             timestamp: Self::timestamp(),
         }
     }
 
-    #[allow(dead_code)]
     pub fn heartbeat() -> Self {
         Self::Heartbeat
     }
 
+    pub fn presence(client_id: ClientId, nickname: &str, event: PresenceEvent) -> Self {
+        Self::Presence {
+            client_id,
+            nickname: nickname.to_string(),
+            event,
+            timestamp: Self::timestamp(),
+        }
+    }
+
+    pub fn who_request() -> Self {
+        Self::WhoRequest
+    }
+
+    pub fn set_status(status: UserStatus, status_text: Option<&str>) -> Self {
+        Self::SetStatus {
+            status,
+            status_text: status_text.map(str::to_string),
+        }
+    }
+
+    pub fn typing(client_id: ClientId, room: &str) -> Self {
+        Self::Typing {
+            client_id,
+            room: room.to_string(),
+        }
+    }
+
+    pub fn edit(message_id: MessageId, new_content: &str) -> Self {
+        Self::Edit {
+            message_id,
+            new_content: new_content.to_string(),
+            room: default_room(),
+        }
+    }
+
+    pub fn delete(message_id: MessageId) -> Self {
+        Self::Delete { message_id, room: default_room() }
+    }
+
+    pub fn grant_role(client_id: ClientId, role: Role, room: Option<&str>) -> Self {
+        Self::GrantRole {
+            client_id,
+            role,
+            room: room.map(str::to_string),
+        }
+    }
+
+    pub fn revoke_role(client_id: ClientId, room: Option<&str>) -> Self {
+        Self::RevokeRole {
+            client_id,
+            room: room.map(str::to_string),
+        }
+    }
+
+    pub fn history_request(before: Option<MessageId>, limit: Option<usize>) -> Self {
+        Self::HistoryRequest { before, limit }
+    }
+
+    pub fn room_list_request() -> Self {
+        Self::RoomListRequest
+    }
+
+    pub fn request(request_id: RequestId, request: Message) -> Self {
+        Self::Request {
+            request_id,
+            request: Box::new(request),
+        }
+    }
+
+    pub fn thread_request(message_id: MessageId) -> Self {
+        Self::ThreadRequest { message_id }
+    }
+
+    pub fn react(message_id: MessageId, emoji: &str) -> Self {
+        Self::React {
+            message_id,
+            emoji: emoji.to_string(),
+        }
+    }
+
+    pub fn unreact(message_id: MessageId, emoji: &str) -> Self {
+        Self::Unreact {
+            message_id,
+            emoji: emoji.to_string(),
+        }
+    }
+
     pub fn timestamp() -> i64 {
         SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -0,0 +1,172 @@
+use anyhow::{Result, anyhow};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use crate::chat::{ClientId, RoomId};
+use crate::storage::{load_json, save_json};
+
+pub const MAX_MUTE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+pub const MAX_SLOW_MODE: Duration = Duration::from_secs(60 * 60);
+
+// Mutes and slow-mode bookkeeping live in memory, bans are saved as JSON when backed by files.
+// Anyone can make up a new client id, so a ban also covers the address the client connected from,
+// remembered with the id it was banned for so unbanning the id lifts both
+pub struct Moderation {
+    mutes: HashMap<(RoomId, ClientId), Instant>,
+    last_posts: HashMap<(RoomId, ClientId), Instant>,
+    bans: HashSet<ClientId>,
+    bans_path: Option<PathBuf>,
+    address_bans: HashMap<IpAddr, ClientId>,
+    address_bans_path: Option<PathBuf>,
+}
+
+impl Default for Moderation {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+impl Moderation {
+    pub fn in_memory() -> Self {
+        Self {
+            mutes: HashMap::new(),
+            last_posts: HashMap::new(),
+            bans: HashSet::new(),
+            bans_path: None,
+            address_bans: HashMap::new(),
+            address_bans_path: None,
+        }
+    }
+
+    pub fn load(bans_path: PathBuf, address_bans_path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            bans: load_json(&bans_path)?,
+            bans_path: Some(bans_path),
+            address_bans: load_json(&address_bans_path)?,
+            address_bans_path: Some(address_bans_path),
+            ..Self::in_memory()
+        })
+    }
+
+    pub fn mute(&mut self, room: &str, client_id: ClientId, duration: Duration) {
+        self.mutes.insert((room.to_string(), client_id), Instant::now() + duration);
+    }
+
+    pub fn unmute(&mut self, room: &str, client_id: ClientId) -> bool {
+        self.mutes.remove(&(room.to_string(), client_id)).is_some_and(|until| until > Instant::now())
+    }
+
+    // Time left on the client's mute in `room`, expired mutes are dropped
+    pub fn muted_for(&mut self, room: &str, client_id: ClientId) -> Option<Duration> {
+        let key = (room.to_string(), client_id);
+        let remaining = self.mutes.get(&key)?.checked_duration_since(Instant::now());
+        if remaining.is_none() {
+            self.mutes.remove(&key);
+        }
+        remaining
+    }
+
+    // The time left to wait if the client posted in `room` less than `interval` ago
+    pub fn slow_mode_wait(&self, room: &str, client_id: ClientId, interval: Duration) -> Option<Duration> {
+        let last = self.last_posts.get(&(room.to_string(), client_id))?;
+        let wait = interval.saturating_sub(last.elapsed());
+        (!wait.is_zero()).then_some(wait)
+    }
+
+    // Called once a post was accepted, so refused messages don't make the client wait
+    pub fn record_post(&mut self, room: &str, client_id: ClientId) {
+        self.last_posts.insert((room.to_string(), client_id), Instant::now());
+    }
+
+    // Slow mode only needs the last post while the client is connected; mutes outlive the session
+    pub fn forget(&mut self, client_id: &ClientId) {
+        self.last_posts.retain(|(_, poster), _| poster != client_id);
+    }
+
+    pub fn is_banned(&self, client_id: &ClientId) -> bool {
+        self.bans.contains(client_id)
+    }
+
+    pub fn is_address_banned(&self, address: &IpAddr) -> bool {
+        self.address_bans.contains_key(address)
+    }
+
+    pub fn ban(&mut self, client_id: ClientId, address: Option<IpAddr>) -> Result<bool> {
+        let added = self.bans.insert(client_id);
+        if added {
+            self.save_bans()?;
+        }
+        if let Some(address) = address
+            && self.address_bans.insert(address, client_id) != Some(client_id)
+        {
+            self.save_address_bans()?;
+        }
+        Ok(added)
+    }
+
+    pub fn unban(&mut self, client_id: &ClientId) -> Result<bool> {
+        let removed = self.bans.remove(client_id);
+        if removed {
+            self.save_bans()?;
+        }
+        let addresses = self.address_bans.len();
+        self.address_bans.retain(|_, banned| banned != client_id);
+        if self.address_bans.len() != addresses {
+            self.save_address_bans()?;
+        }
+        Ok(removed)
+    }
+
+    fn save_bans(&self) -> Result<()> {
+        match &self.bans_path {
+            Some(path) => save_json(path, &self.bans),
+            None => Ok(()),
+        }
+    }
+
+    fn save_address_bans(&self) -> Result<()> {
+        match &self.address_bans_path {
+            Some(path) => save_json(path, &self.address_bans),
+            None => Ok(()),
+        }
+    }
+}
+
+// Accepts a number of seconds or a number followed by s, m, h or d, e.g. "90", "10m", "2h"
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let text = text.trim().to_ascii_lowercase();
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => text.split_at(index),
+        None => (text.as_str(), "s"),
+    };
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(anyhow!("Invalid duration {}, use e.g. 30s, 10m, 2h or 1d", text)),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid duration {}, use e.g. 30s, 10m, 2h or 1d", text))?;
+    Ok(Duration::from_secs(number.saturating_mul(multiplier)))
+}
+
+// Largest two units, e.g. "1h 5m" or "42s"
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs().max(1);
+    let units = [(24 * 60 * 60, "d"), (60 * 60, "h"), (60, "m"), (1, "s")];
+    let parts: Vec<String> = units
+        .iter()
+        .scan(seconds, |left, (size, unit)| {
+            let count = *left / size;
+            *left %= size;
+            Some((count, *unit))
+        })
+        .filter(|(count, _)| *count > 0)
+        .take(2)
+        .map(|(count, unit)| format!("{}{}", count, unit))
+        .collect();
+    parts.join(" ")
+}
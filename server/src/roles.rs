@@ -0,0 +1,160 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use crate::chat::{ClientId, RoomId};
+use crate::storage::{load_json, save_json};
+
+// Ordered from least to most privileged
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Role {
+    Guest,
+    #[default]
+    Member,
+    Moderator,
+    Owner,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Permission {
+    Post,
+    EditOthers,
+    DeleteOthers,
+    Kick,
+    Ban,
+    Mute,
+    SetTopic,
+    ManageRoles,
+    ViewAudit,
+}
+
+impl Role {
+    pub fn permissions(self) -> &'static [Permission] {
+        use Permission::*;
+        match self {
+            Role::Guest => &[],
+            Role::Member => &[Post],
+            Role::Moderator => &[Post, DeleteOthers, Kick, Mute, SetTopic],
+            Role::Owner => &[Post, EditOthers, DeleteOthers, Kick, Ban, Mute, SetTopic, ManageRoles, ViewAudit],
+        }
+    }
+
+    pub fn can(self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Role::Guest => "guest",
+            Role::Member => "member",
+            Role::Moderator => "moderator",
+            Role::Owner => "owner",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "guest" => Ok(Role::Guest),
+            "member" => Ok(Role::Member),
+            "moderator" | "mod" => Ok(Role::Moderator),
+            "owner" => Ok(Role::Owner),
+            _ => Err(anyhow::anyhow!("Unknown role {}, expected guest, member, moderator or owner", name)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Assignments {
+    #[serde(default)]
+    server: HashMap<ClientId, Role>,
+    #[serde(default)]
+    rooms: HashMap<RoomId, HashMap<ClientId, Role>>,
+}
+
+// Role assignments per server and per room, saved as JSON after every change when backed by a file.
+// A room assignment overrides the server one, except that server owners are owners everywhere.
+pub struct RoleStore {
+    assignments: Assignments,
+    path: Option<PathBuf>,
+}
+
+impl Default for RoleStore {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+impl RoleStore {
+    pub fn in_memory() -> Self {
+        Self {
+            assignments: Assignments::default(),
+            path: None,
+        }
+    }
+
+    pub fn load(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            assignments: load_json(&path)?,
+            path: Some(path),
+        })
+    }
+
+    pub fn server_role(&self, client_id: &ClientId) -> Role {
+        self.assignments.server.get(client_id).copied().unwrap_or_default()
+    }
+
+    pub fn role_in(&self, client_id: &ClientId, room: &str) -> Role {
+        let server_role = self.server_role(client_id);
+        if server_role == Role::Owner {
+            return server_role;
+        }
+        self.assignments
+            .rooms
+            .get(room)
+            .and_then(|assigned| assigned.get(client_id))
+            .copied()
+            .unwrap_or(server_role)
+    }
+
+    // `room: None` assigns the server-wide role
+    pub fn assign(&mut self, client_id: ClientId, room: Option<&str>, role: Role) -> Result<()> {
+        match room {
+            Some(room) => self.assignments.rooms.entry(room.to_string()).or_default().insert(client_id, role),
+            None => self.assignments.server.insert(client_id, role),
+        };
+        self.save()
+    }
+
+    // Returns whether there was an assignment to remove
+    pub fn revoke(&mut self, client_id: &ClientId, room: Option<&str>) -> Result<bool> {
+        let removed = match room {
+            Some(room) => self
+                .assignments
+                .rooms
+                .get_mut(room)
+                .and_then(|assigned| assigned.remove(client_id))
+                .is_some(),
+            None => self.assignments.server.remove(client_id).is_some(),
+        };
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    fn save(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => save_json(path, &self.assignments),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use crate::chat::RoomId;
+use crate::storage::{load_json, save_json};
+
+pub const MAX_ROOM_NAME_LEN: usize = 32;
+pub const MAX_TOPIC_LEN: usize = 256;
+pub const MAX_DESCRIPTION_LEN: usize = 1024;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Room {
+    #[serde(default)]
+    pub topic: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    // Minimum seconds between two messages from the same member
+    #[serde(default)]
+    pub slow_mode_secs: Option<u64>,
+}
+
+// Room settings, saved as JSON after every change when backed by a file
+pub struct Rooms {
+    rooms: HashMap<RoomId, Room>,
+    path: Option<PathBuf>,
+}
+
+impl Default for Rooms {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+impl Rooms {
+    pub fn in_memory() -> Self {
+        Self {
+            rooms: HashMap::new(),
+            path: None,
+        }
+    }
+
+    pub fn load(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            rooms: load_json(&path)?,
+            path: Some(path),
+        })
+    }
+
+    pub fn get(&self, room: &str) -> Room {
+        self.rooms.get(room).cloned().unwrap_or_default()
+    }
+
+    // Rooms that have settings, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &RoomId> {
+        self.rooms.keys()
+    }
+
+    pub fn update(&mut self, room: &str, change: impl FnOnce(&mut Room)) -> Result<Room> {
+        let settings = self.rooms.entry(room.to_string()).or_default();
+        change(settings);
+        let updated = settings.clone();
+        self.save()?;
+        Ok(updated)
+    }
+
+    fn save(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => save_json(path, &self.rooms),
+            None => Ok(()),
+        }
+    }
+}
+
+// Accepts `name` or `#name`, room names are lowercase ASCII letters, digits, '_' or '-'
+pub fn normalize_room_name(name: &str) -> Option<String> {
+    let name = name.trim().trim_start_matches('#').to_ascii_lowercase();
+    let valid = !name.is_empty()
+        && name.len() <= MAX_ROOM_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    valid.then_some(name)
+}
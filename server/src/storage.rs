@@ -0,0 +1,21 @@
+use anyhow::Result;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::path::Path;
+
+// A missing file reads as the default value
+pub fn load_json<T: Default + DeserializeOwned>(path: &Path) -> Result<T> {
+    match std::fs::read_to_string(path) {
+        Ok(json) => Ok(serde_json::from_str(&json)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Written to a temporary file first so a crash never leaves a truncated file behind
+pub fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let temporary = path.with_extension("json.tmp");
+    std::fs::write(&temporary, serde_json::to_string_pretty(value)?)?;
+    std::fs::rename(&temporary, path)?;
+    Ok(())
+}
@@ -0,0 +1,311 @@
+use client_lib::{ClientEvent, display_name};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use server::{ClientId, Message, MessageId, PresenceEvent, RoomId, RoomSummary, StoredMessage, UserInfo};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+// Messages fetched per history request, both on entering a room and when scrolling past the top
+pub const HISTORY_PAGE: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    Connecting,
+    Connected,
+    Reconnecting { attempt: u32, delay: Duration },
+    Disconnected,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub id: Option<MessageId>,
+    pub timestamp: Option<i64>,
+    // None for notices from the server or the client itself
+    pub author: Option<ClientId>,
+    pub content: String,
+    pub edited: bool,
+    pub deleted: bool,
+}
+
+impl Entry {
+    fn notice(content: impl Into<String>) -> Self {
+        Self {
+            id: None,
+            timestamp: None,
+            author: None,
+            content: content.into(),
+            edited: false,
+            deleted: false,
+        }
+    }
+
+    fn stored(message: StoredMessage) -> Self {
+        Self {
+            id: Some(message.id),
+            timestamp: Some(message.timestamp),
+            author: Some(message.client_id),
+            content: message.content,
+            edited: message.edited_at.is_some(),
+            deleted: message.deleted,
+        }
+    }
+}
+
+// What the terminal loop has to do with the client after a key press or event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    // A chat message, or a slash command when it starts with '/'
+    Send(String),
+    Join(RoomId),
+    // Just moved to a room: load its latest messages and refresh the room and member lists
+    EnterRoom,
+    // The page of history before message `before`, for scrolling past the oldest loaded message
+    LoadOlder { before: MessageId },
+    Quit,
+}
+
+// Everything the terminal client shows, kept apart from the terminal so it can be driven
+// by events and keys and rendered to any backend
+pub struct App {
+    own_id: ClientId,
+    pub status: ConnectionStatus,
+    pub room: Option<RoomId>,
+    pub topic: Option<String>,
+    pub rooms: Vec<RoomSummary>,
+    pub entries: Vec<Entry>,
+    nicknames: HashMap<ClientId, String>,
+    pub input: String,
+    // Lines scrolled up from the bottom of the message list, 0 follows new messages
+    pub scroll: usize,
+    // Set while rendering, so scrolling knows where the top is
+    pub(crate) max_scroll: usize,
+    pub(crate) page_height: usize,
+    // No more history before the oldest loaded message
+    history_exhausted: bool,
+    loading_older: bool,
+}
+
+impl App {
+    pub fn new(own_id: ClientId) -> Self {
+        Self {
+            own_id,
+            status: ConnectionStatus::Connecting,
+            room: None,
+            topic: None,
+            rooms: Vec::new(),
+            entries: Vec::new(),
+            nicknames: HashMap::new(),
+            input: String::new(),
+            scroll: 0,
+            max_scroll: 0,
+            page_height: 1,
+            history_exhausted: false,
+            loading_older: false,
+        }
+    }
+
+    pub fn own_id(&self) -> ClientId {
+        self.own_id
+    }
+
+    // Nickname of a known member, falling back to the short id
+    pub fn display_name(&self, client_id: &ClientId) -> String {
+        display_name(self.nicknames.get(client_id).map(String::as_str), client_id)
+    }
+
+    pub fn notice(&mut self, content: impl Into<String>) {
+        self.entries.push(Entry::notice(content));
+    }
+
+    pub fn handle_event(&mut self, event: ClientEvent) -> Option<Action> {
+        match event {
+            ClientEvent::Message(message) => return self.handle_message(message),
+            ClientEvent::Lagged { skipped } => self.notice(format!("Missed {} messages", skipped)),
+            ClientEvent::ConnectFailed { reason } => self.notice(format!("Could not connect: {}", reason)),
+            ClientEvent::Reconnecting { attempt, delay } => {
+                self.status = ConnectionStatus::Reconnecting { attempt, delay };
+            }
+            // The client rejoins the previous room on its own; until the server confirms a room,
+            // nothing shown belongs to one, and the confirmation loads it afresh
+            ClientEvent::Reconnected => {
+                self.status = ConnectionStatus::Connected;
+                self.room = None;
+                self.topic = None;
+                self.entries.clear();
+                self.scroll = 0;
+                self.notice("Reconnected");
+            }
+            ClientEvent::Disconnected => {
+                self.status = ConnectionStatus::Disconnected;
+                self.notice("Disconnected from the server");
+            }
+        }
+        None
+    }
+
+    fn handle_message(&mut self, message: Message) -> Option<Action> {
+        match message {
+            Message::Chat { content, client_id, timestamp, id, room, .. } if self.room.as_ref() == Some(&room) => {
+                self.entries.push(Entry {
+                    id,
+                    timestamp: Some(timestamp),
+                    author: Some(client_id),
+                    content,
+                    edited: false,
+                    deleted: false,
+                });
+            }
+            Message::Credentials { token } => self.notice(format!(
+                "To connect as yourself again, set CHAT_CLIENT_ID={} CHAT_TOKEN={}",
+                self.own_id, token
+            )),
+            Message::Edit { message_id, new_content, .. } => {
+                if let Some(entry) = self.entry_mut(message_id) {
+                    entry.content = new_content;
+                    entry.edited = true;
+                }
+            }
+            Message::Delete { message_id, .. } => {
+                if let Some(entry) = self.entry_mut(message_id) {
+                    entry.content.clear();
+                    entry.deleted = true;
+                }
+            }
+            Message::System { content } => self.notice(content),
+            Message::Nack { reason, .. } => self.notice(format!("Not sent: {}", reason)),
+            Message::Presence { client_id, nickname, event, .. } => {
+                let verb = match event {
+                    PresenceEvent::Joined => "joined",
+                    PresenceEvent::Left => "left",
+                };
+                self.notice(format!("{} {}", nickname, verb));
+                self.nicknames.insert(client_id, nickname);
+            }
+            Message::WhoList { clients } => self.set_members(clients),
+            Message::NicknameChanged { client_id, nickname } => {
+                let previous = self.display_name(&client_id);
+                self.notice(format!("{} is now known as {}", previous, nickname));
+                self.nicknames.insert(client_id, nickname);
+            }
+            Message::RoomChanged { room, topic, .. } => {
+                // Notices from before the first room, like the MOTD, stay
+                if self.room.is_some() {
+                    self.entries.clear();
+                }
+                self.status = ConnectionStatus::Connected;
+                self.room = Some(room);
+                self.topic = topic;
+                self.scroll = 0;
+                self.history_exhausted = false;
+                self.loading_older = false;
+                return Some(Action::EnterRoom);
+            }
+            Message::RoomUpdated { room, topic, .. } if self.room.as_ref() == Some(&room) => self.topic = topic,
+            _ => {}
+        }
+        None
+    }
+
+    fn entry_mut(&mut self, message_id: MessageId) -> Option<&mut Entry> {
+        self.entries.iter_mut().find(|entry| entry.id == Some(message_id))
+    }
+
+    pub fn set_members(&mut self, members: Vec<UserInfo>) {
+        for member in members {
+            self.nicknames.insert(member.client_id, member.nickname);
+        }
+    }
+
+    pub fn set_rooms(&mut self, rooms: Vec<RoomSummary>) {
+        self.rooms = rooms;
+    }
+
+    // Puts a page of `room`'s history, oldest first, before the loaded messages.
+    // Messages that arrived live while the page was requested are not repeated
+    pub fn load_history(&mut self, room: &str, messages: Vec<StoredMessage>) {
+        self.loading_older = false;
+        if self.room.as_deref() != Some(room) {
+            return;
+        }
+        if messages.len() < HISTORY_PAGE {
+            self.history_exhausted = true;
+        }
+        let loaded: HashSet<MessageId> = self.entries.iter().filter_map(|entry| entry.id).collect();
+        let older: Vec<Entry> = messages
+            .into_iter()
+            .filter(|message| !loaded.contains(&message.id))
+            .map(Entry::stored)
+            .collect();
+        self.entries.splice(0..0, older);
+    }
+
+    // Lets scrolling up ask again
+    pub fn history_failed(&mut self, reason: impl Into<String>) {
+        self.loading_older = false;
+        self.notice(reason);
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<Action> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') if ctrl => return Some(Action::Quit),
+            KeyCode::Esc => return Some(Action::Quit),
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Enter => return self.submit(),
+            KeyCode::PageUp => return self.scroll_up(self.page_height),
+            KeyCode::Up => return self.scroll_up(1),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(self.page_height),
+            KeyCode::Down => self.scroll = self.scroll.saturating_sub(1),
+            KeyCode::End => self.scroll = 0,
+            KeyCode::Tab => return self.switch_room(1),
+            KeyCode::BackTab => return self.switch_room(-1),
+            _ => {}
+        }
+        None
+    }
+
+    fn submit(&mut self) -> Option<Action> {
+        let line = std::mem::take(&mut self.input);
+        let line = line.trim();
+        if line.is_empty() {
+            return None;
+        }
+        if line == "/quit" {
+            return Some(Action::Quit);
+        }
+        self.scroll = 0;
+        Some(Action::Send(line.to_string()))
+    }
+
+    // Past the top of the loaded messages, asks for the page before them
+    fn scroll_up(&mut self, lines: usize) -> Option<Action> {
+        if self.scroll < self.max_scroll {
+            self.scroll = (self.scroll + lines).min(self.max_scroll);
+            return None;
+        }
+        if self.history_exhausted || self.loading_older {
+            return None;
+        }
+        let before = self.entries.iter().find_map(|entry| entry.id)?;
+        self.loading_older = true;
+        Some(Action::LoadOlder { before })
+    }
+
+    // Moves to the next (or previous) room of the room list, wrapping around
+    fn switch_room(&mut self, step: isize) -> Option<Action> {
+        if self.rooms.is_empty() {
+            return None;
+        }
+        let count = self.rooms.len() as isize;
+        let current = self
+            .rooms
+            .iter()
+            .position(|summary| Some(&summary.room) == self.room.as_ref())
+            .map_or(if step > 0 { -1 } else { 0 }, |index| index as isize);
+        let next = (current + step).rem_euclid(count) as usize;
+        let room = &self.rooms[next].room;
+        (Some(room) != self.room.as_ref()).then(|| Action::Join(room.clone()))
+    }
+}
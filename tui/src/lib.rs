@@ -0,0 +1,5 @@
+mod app;
+mod view;
+
+pub use app::{Action, App, ConnectionStatus, Entry, HISTORY_PAGE};
+pub use view::draw;
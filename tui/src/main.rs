@@ -0,0 +1,125 @@
+use anyhow::Result;
+use client_lib::{ClientHandle, EventStream, ReconnectPolicy};
+use crossterm::event::{Event, EventStream as TerminalEvents, KeyEventKind};
+use ratatui::DefaultTerminal;
+use server::{MessageId, RoomId, RoomSummary, StoredMessage, UserInfo};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio_stream::StreamExt;
+use tui::{Action, App, HISTORY_PAGE, draw};
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+// Answers to queries made in the background, so the terminal stays responsive
+enum Fetched {
+    History { room: RoomId, messages: Vec<StoredMessage> },
+    Rooms(Vec<RoomSummary>),
+    Members(Vec<UserInfo>),
+    HistoryFailed(String),
+    Failed(String),
+}
+
+// Usage: tui [address], with CHAT_NICKNAME, CHAT_CLIENT_ID and CHAT_TOKEN read like the GUI client does
+#[tokio::main]
+async fn main() -> Result<()> {
+    let address = std::env::args().nth(1).unwrap_or_else(|| DEFAULT_ADDRESS.to_string());
+    let mut builder = ClientHandle::builder(address)
+        .echo(true)
+        .heartbeat_interval(HEARTBEAT_INTERVAL)
+        .reconnect(ReconnectPolicy::backoff(Duration::from_secs(1), Duration::from_secs(30)));
+    if let Ok(nickname) = std::env::var("CHAT_NICKNAME") {
+        builder = builder.nickname(nickname);
+    }
+    if let Some(client_id) = std::env::var("CHAT_CLIENT_ID").ok().and_then(|id| id.parse().ok()) {
+        builder = builder.client_id(client_id);
+    }
+    if let Ok(token) = std::env::var("CHAT_TOKEN") {
+        builder = builder.token(token);
+    }
+    // Connection problems show up in the status line instead of keeping the terminal from opening
+    let (client, events) = builder.connect_in_background()?;
+    let app = App::new(client.client_id());
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, client, events, app).await;
+    ratatui::restore();
+    result
+}
+
+async fn run(terminal: &mut DefaultTerminal, client: ClientHandle, mut events: EventStream, mut app: App) -> Result<()> {
+    let mut keys = TerminalEvents::new();
+    let (fetched_tx, mut fetched_rx) = mpsc::unbounded_channel::<Fetched>();
+
+    loop {
+        terminal.draw(|frame| draw(frame, &mut app))?;
+
+        let action = tokio::select! {
+            Some(key) = keys.next() => match key? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => app.handle_key(key),
+                _ => None,
+            },
+            Some(event) = events.next() => app.handle_event(event),
+            Some(fetched) = fetched_rx.recv() => {
+                match fetched {
+                    Fetched::History { room, messages } => app.load_history(&room, messages),
+                    Fetched::Rooms(rooms) => app.set_rooms(rooms),
+                    Fetched::Members(members) => app.set_members(members),
+                    Fetched::HistoryFailed(reason) => app.history_failed(reason),
+                    Fetched::Failed(reason) => app.notice(reason),
+                }
+                None
+            }
+        };
+
+        match action {
+            Some(Action::Quit) => return Ok(()),
+            Some(Action::Send(line)) => {
+                let sent = if line.starts_with('/') {
+                    client.send_command(&line).await
+                } else {
+                    // The server echoes the message back, so the Ack is not needed
+                    client.send_message(&line).await.map(|_| ())
+                };
+                if let Err(e) = sent {
+                    app.notice(e.to_string());
+                }
+            }
+            Some(Action::Join(room)) => {
+                if let Err(e) = client.send_command(&format!("/join {}", room)).await {
+                    app.notice(e.to_string());
+                }
+            }
+            Some(Action::EnterRoom) => {
+                spawn_history(&client, &fetched_tx, None);
+                let (rooms_client, rooms_tx) = (client.clone(), fetched_tx.clone());
+                tokio::spawn(async move {
+                    let fetched = match rooms_client.rooms().await {
+                        Ok(rooms) => Fetched::Rooms(rooms),
+                        Err(e) => Fetched::Failed(format!("Could not list rooms: {}", e)),
+                    };
+                    let _ = rooms_tx.send(fetched);
+                });
+                let (who_client, who_tx) = (client.clone(), fetched_tx.clone());
+                tokio::spawn(async move {
+                    if let Ok(members) = who_client.who().await {
+                        let _ = who_tx.send(Fetched::Members(members));
+                    }
+                });
+            }
+            Some(Action::LoadOlder { before }) => spawn_history(&client, &fetched_tx, Some(before)),
+            None => {}
+        }
+    }
+}
+
+fn spawn_history(client: &ClientHandle, fetched_tx: &UnboundedSender<Fetched>, before: Option<MessageId>) {
+    let (client, fetched_tx) = (client.clone(), fetched_tx.clone());
+    tokio::spawn(async move {
+        let fetched = match client.history(before, Some(HISTORY_PAGE)).await {
+            Ok((room, messages)) => Fetched::History { room, messages },
+            Err(e) => Fetched::HistoryFailed(format!("Could not load history: {}", e)),
+        };
+        let _ = fetched_tx.send(fetched);
+    });
+}
@@ -0,0 +1,135 @@
+use client_lib::clock;
+use ratatui::Frame;
+use ratatui::layout::{Constraint, Layout, Position, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph, Wrap};
+
+use crate::app::{App, ConnectionStatus, Entry};
+
+const ROOMS_WIDTH: u16 = 20;
+
+pub fn draw(frame: &mut Frame, app: &mut App) {
+    let [header, body, input] =
+        Layout::vertical([Constraint::Length(1), Constraint::Min(1), Constraint::Length(3)]).areas(frame.area());
+    let [messages, rooms] = Layout::horizontal([Constraint::Min(1), Constraint::Length(ROOMS_WIDTH)]).areas(body);
+
+    draw_header(frame, app, header);
+    draw_messages(frame, app, messages);
+    draw_rooms(frame, app, rooms);
+    draw_input(frame, app, input);
+}
+
+fn draw_header(frame: &mut Frame, app: &App, area: Rect) {
+    let mut title = vec![Span::styled(
+        format!("#{}", app.room.as_deref().unwrap_or("…")),
+        Style::default().add_modifier(Modifier::BOLD),
+    )];
+    if let Some(topic) = &app.topic {
+        title.push(Span::raw(format!(" — {}", topic)));
+    }
+    let (status, color) = match app.status {
+        ConnectionStatus::Connecting => ("connecting…".to_string(), Color::Yellow),
+        ConnectionStatus::Connected => ("connected".to_string(), Color::Green),
+        ConnectionStatus::Reconnecting { attempt, delay } => {
+            (format!("reconnecting in {}s (attempt {})", delay.as_secs(), attempt), Color::Yellow)
+        }
+        ConnectionStatus::Disconnected => ("disconnected".to_string(), Color::Red),
+    };
+
+    let [left, right] =
+        Layout::horizontal([Constraint::Min(1), Constraint::Length(status.chars().count() as u16)]).areas(area);
+    frame.render_widget(Paragraph::new(Line::from(title)), left);
+    frame.render_widget(Paragraph::new(Span::styled(status, Style::default().fg(color))), right);
+}
+
+fn draw_messages(frame: &mut Frame, app: &mut App, area: Rect) {
+    let block = Block::default().borders(Borders::ALL);
+    let inner = block.inner(area);
+    let wrap = Wrap { trim: false };
+    let mut lines: Vec<Line> = app.entries.iter().map(|entry| entry_line(app, entry)).collect();
+    let heights: Vec<usize> =
+        lines.iter().map(|line| Paragraph::new(line.clone()).wrap(wrap).line_count(inner.width)).collect();
+
+    // Scrolling counts wrapped lines from the bottom, so the newest message stays in view
+    let total: usize = heights.iter().sum();
+    let height = inner.height as usize;
+    app.page_height = height.max(1);
+    app.max_scroll = total.saturating_sub(height);
+    app.scroll = app.scroll.min(app.max_scroll);
+    let top = app.max_scroll - app.scroll;
+
+    // Entries above the one holding the top line are left out, the paragraph's own scroll offset is a u16
+    let mut first = 0;
+    let mut skipped = 0;
+    while first < heights.len() && skipped + heights[first] <= top {
+        skipped += heights[first];
+        first += 1;
+    }
+    let offset = (top - skipped).min(u16::MAX as usize) as u16;
+
+    let block = if app.scroll > 0 {
+        block.title_bottom(Line::from(format!(" ↓ {} more ", app.scroll)).right_aligned())
+    } else {
+        block
+    };
+    let paragraph = Paragraph::new(lines.split_off(first)).wrap(wrap);
+    frame.render_widget(paragraph.block(block).scroll((offset, 0)), area);
+}
+
+fn entry_line<'a>(app: &App, entry: &'a Entry) -> Line<'a> {
+    let mut spans = Vec::new();
+    if let Some(timestamp) = entry.timestamp {
+        spans.push(Span::styled(format!("{} ", clock(timestamp)), Style::default().fg(Color::DarkGray)));
+    }
+    match entry.author {
+        Some(author) => {
+            let color = if author == app.own_id() { Color::Cyan } else { Color::Magenta };
+            let name = format!("{}: ", app.display_name(&author));
+            spans.push(Span::styled(name, Style::default().fg(color).add_modifier(Modifier::BOLD)));
+            if entry.deleted {
+                spans.push(Span::styled("message deleted", Style::default().add_modifier(Modifier::ITALIC)));
+            } else {
+                spans.push(Span::raw(entry.content.as_str()));
+                if entry.edited {
+                    spans.push(Span::styled(" (edited)", Style::default().fg(Color::DarkGray)));
+                }
+            }
+        }
+        None => spans.push(Span::styled(entry.content.as_str(), Style::default().fg(Color::Yellow))),
+    }
+    Line::from(spans)
+}
+
+fn draw_rooms(frame: &mut Frame, app: &App, area: Rect) {
+    let items: Vec<ListItem> = app
+        .rooms
+        .iter()
+        .map(|summary| {
+            let label = format!("#{} ({})", summary.room, summary.members);
+            if app.room.as_ref() == Some(&summary.room) {
+                ListItem::new(label).style(Style::default().add_modifier(Modifier::REVERSED))
+            } else {
+                ListItem::new(label)
+            }
+        })
+        .collect();
+    let block = Block::default().borders(Borders::ALL).title(" Rooms ");
+    frame.render_widget(List::new(items).block(block), area);
+}
+
+fn draw_input(frame: &mut Frame, app: &App, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Enter send · Tab switch room · PgUp/PgDn scroll · Esc quit ");
+    let inner = block.inner(area);
+
+    // Long input scrolls horizontally to keep the end visible
+    let width = inner.width.saturating_sub(1) as usize;
+    let length = app.input.chars().count();
+    let visible: String = app.input.chars().skip(length.saturating_sub(width)).collect();
+    let cursor = Position::new(inner.x + visible.chars().count() as u16, inner.y);
+
+    frame.render_widget(Paragraph::new(visible).block(block), area);
+    frame.set_cursor_position(cursor);
+}
@@ -0,0 +1,266 @@
+use client_lib::ClientEvent;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::Terminal;
+use ratatui::backend::TestBackend;
+use server::{ClientId, Message, RoomSummary, StoredMessage, UserInfo, UserStatus};
+use std::time::Duration;
+use tui::{Action, App, ConnectionStatus, HISTORY_PAGE, draw};
+
+fn id(n: u128) -> ClientId {
+    ClientId::from_u128(n)
+}
+
+fn message(event: Message) -> ClientEvent {
+    ClientEvent::Message(event)
+}
+
+fn chat(from: ClientId, content: &str, id: u64) -> ClientEvent {
+    message(Message::Chat {
+        content: content.to_string(),
+        client_id: from,
+        timestamp: 3600 * 13 + 60 * 7,
+        id: Some(id),
+        client_msg_id: None,
+        reply_to: None,
+        mentions: Vec::new(),
+        room: "general".to_string(),
+    })
+}
+
+fn stored(id: u64, content: &str) -> StoredMessage {
+    StoredMessage {
+        id,
+        client_id: self::id(2),
+        content: content.to_string(),
+        timestamp: 0,
+        room: "general".to_string(),
+        reply_to: None,
+        mentions: Vec::new(),
+        edited_at: None,
+        deleted: false,
+        reactions: Default::default(),
+    }
+}
+
+fn enter_general(app: &mut App) {
+    let entered = app.handle_event(message(Message::RoomChanged {
+        room: "general".to_string(),
+        topic: Some("Say hi".to_string()),
+        description: None,
+    }));
+    assert_eq!(entered, Some(Action::EnterRoom));
+    app.set_rooms(vec![
+        RoomSummary { room: "general".to_string(), topic: Some("Say hi".to_string()), members: 2 },
+        RoomSummary { room: "rust".to_string(), topic: None, members: 0 },
+    ]);
+    app.set_members(vec![UserInfo {
+        client_id: id(2),
+        nickname: "alice".to_string(),
+        status: UserStatus::Online,
+        status_text: None,
+    }]);
+}
+
+fn key(code: KeyCode) -> KeyEvent {
+    KeyEvent::new(code, KeyModifiers::NONE)
+}
+
+fn type_line(app: &mut App, line: &str) -> Option<Action> {
+    for c in line.chars() {
+        app.handle_key(key(KeyCode::Char(c)));
+    }
+    app.handle_key(key(KeyCode::Enter))
+}
+
+fn render(app: &mut App, width: u16, height: u16) -> Vec<String> {
+    let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+    terminal.draw(|frame| draw(frame, app)).unwrap();
+    let buffer = terminal.backend().buffer();
+    (0..height)
+        .map(|y| (0..width).map(|x| buffer[(x, y)].symbol()).collect::<String>())
+        .collect()
+}
+
+fn screen_contains(screen: &[String], text: &str) -> bool {
+    screen.iter().any(|line| line.contains(text))
+}
+
+#[test]
+fn renders_room_status_messages_and_rooms() {
+    let mut app = App::new(id(1));
+    let screen = render(&mut app, 60, 12);
+    assert!(screen[0].contains("connecting"));
+
+    enter_general(&mut app);
+    app.handle_event(chat(id(2), "hello there", 1));
+    app.handle_event(message(Message::System { content: "Welcome".to_string() }));
+
+    let screen = render(&mut app, 60, 12);
+    assert!(screen[0].starts_with("#general — Say hi"));
+    assert!(screen[0].trim_end().ends_with("connected"));
+    assert!(screen_contains(&screen, "13:07 alice: hello there"));
+    assert!(screen_contains(&screen, "Welcome"));
+    assert!(screen_contains(&screen, "#general (2)"));
+    assert!(screen_contains(&screen, "#rust (0)"));
+}
+
+#[test]
+fn edits_and_deletes_update_shown_messages() {
+    let mut app = App::new(id(1));
+    enter_general(&mut app);
+    app.handle_event(chat(id(2), "first", 1));
+    app.handle_event(chat(id(2), "second", 2));
+    app.handle_event(message(Message::edit(1, "fixed")));
+    app.handle_event(message(Message::delete(2)));
+
+    let screen = render(&mut app, 60, 12);
+    assert!(screen_contains(&screen, "alice: fixed (edited)"));
+    assert!(screen_contains(&screen, "alice: message deleted"));
+    assert!(!screen_contains(&screen, "second"));
+}
+
+#[test]
+fn shows_reconnecting_and_disconnected_status() {
+    let mut app = App::new(id(1));
+    enter_general(&mut app);
+
+    app.handle_event(ClientEvent::Reconnecting { attempt: 2, delay: Duration::from_secs(4) });
+    assert!(render(&mut app, 60, 12)[0].contains("reconnecting in 4s (attempt 2)"));
+
+    app.handle_event(ClientEvent::ConnectFailed { reason: "connection refused".to_string() });
+    app.handle_event(ClientEvent::Disconnected);
+    assert_eq!(app.status, ConnectionStatus::Disconnected);
+    let screen = render(&mut app, 60, 12);
+    assert!(screen[0].contains("disconnected"));
+    assert!(screen_contains(&screen, "Could not connect: connection refused"));
+    assert!(screen_contains(&screen, "Disconnected from the server"));
+}
+
+#[test]
+fn reconnecting_drops_the_room_until_the_server_confirms_one() {
+    let mut app = App::new(id(1));
+    enter_general(&mut app);
+    app.handle_event(chat(id(2), "before the drop", 1));
+
+    app.handle_event(ClientEvent::Reconnecting { attempt: 1, delay: Duration::from_secs(1) });
+    app.handle_event(ClientEvent::Reconnected);
+    assert_eq!(app.status, ConnectionStatus::Connected);
+    assert_eq!(app.room, None);
+    assert!(!screen_contains(&render(&mut app, 60, 12), "before the drop"));
+
+    let rejoined = app.handle_event(message(Message::RoomChanged {
+        room: "general".to_string(),
+        topic: None,
+        description: None,
+    }));
+    assert_eq!(rejoined, Some(Action::EnterRoom));
+    assert_eq!(app.room.as_deref(), Some("general"));
+    assert!(screen_contains(&render(&mut app, 60, 12), "Reconnected"));
+}
+
+#[test]
+fn scrolls_to_the_right_place_past_u16_lines() {
+    let mut app = App::new(id(1));
+    for n in 0..70_000 {
+        app.notice(format!("message {}", n));
+    }
+    assert!(screen_contains(&render(&mut app, 60, 12), "message 69999"));
+
+    // 6 lines of messages fit, the top one is line 66000
+    app.scroll = 70_000 - 6 - 66_000;
+    let screen = render(&mut app, 60, 12);
+    assert!(screen[2].contains("message 66000"), "{:?}", screen);
+
+    app.scroll = usize::MAX;
+    assert!(render(&mut app, 60, 12)[2].contains("message 0 "));
+}
+
+#[test]
+fn input_line_submits_messages_and_commands() {
+    let mut app = App::new(id(1));
+    enter_general(&mut app);
+
+    for c in "draft".chars() {
+        app.handle_key(key(KeyCode::Char(c)));
+    }
+    app.handle_key(key(KeyCode::Backspace));
+    assert!(screen_contains(&render(&mut app, 60, 12), "│draf"));
+
+    app.handle_key(key(KeyCode::Enter));
+    assert_eq!(app.input, "");
+    assert_eq!(type_line(&mut app, "/topic Rust"), Some(Action::Send("/topic Rust".to_string())));
+    assert_eq!(type_line(&mut app, "   "), None);
+    assert_eq!(type_line(&mut app, "/quit"), Some(Action::Quit));
+    assert_eq!(app.handle_key(key(KeyCode::Esc)), Some(Action::Quit));
+}
+
+#[test]
+fn tab_switches_between_listed_rooms() {
+    let mut app = App::new(id(1));
+    enter_general(&mut app);
+
+    assert_eq!(app.handle_key(key(KeyCode::Tab)), Some(Action::Join("rust".to_string())));
+    assert_eq!(app.handle_key(key(KeyCode::BackTab)), Some(Action::Join("rust".to_string())));
+
+    app.handle_event(message(Message::RoomChanged { room: "rust".to_string(), topic: None, description: None }));
+    assert!(app.entries.is_empty());
+    assert_eq!(app.handle_key(key(KeyCode::Tab)), Some(Action::Join("general".to_string())));
+}
+
+#[test]
+fn scrolling_past_the_top_loads_older_history() {
+    let mut app = App::new(id(1));
+    enter_general(&mut app);
+
+    let page: Vec<StoredMessage> = (51..=100).map(|n| stored(n, &format!("message {}", n))).collect();
+    app.load_history("general", page);
+    let screen = render(&mut app, 60, 12);
+    assert!(screen_contains(&screen, "message 100"));
+    assert!(!screen_contains(&screen, "message 51"));
+
+    // 50 lines in a 6 line view: one page up, then all the way
+    app.handle_key(key(KeyCode::PageUp));
+    let screen = render(&mut app, 60, 12);
+    assert!(screen_contains(&screen, "message 94"));
+    assert!(!screen_contains(&screen, "message 100"));
+    assert!(screen_contains(&screen, "↓ 6 more"));
+
+    for _ in 0..7 {
+        assert_eq!(app.handle_key(key(KeyCode::PageUp)), None);
+    }
+    assert!(screen_contains(&render(&mut app, 60, 12), "message 51"));
+    assert_eq!(app.handle_key(key(KeyCode::PageUp)), Some(Action::LoadOlder { before: 51 }));
+    // Only one request at a time
+    assert_eq!(app.handle_key(key(KeyCode::PageUp)), None);
+
+    let older: Vec<StoredMessage> = (41..=50).map(|n| stored(n, &format!("message {}", n))).collect();
+    assert!(older.len() < HISTORY_PAGE);
+    app.load_history("general", older);
+    assert_eq!(app.entries.first().and_then(|entry| entry.id), Some(41));
+    // Each render updates how far up there is to go
+    for _ in 0..3 {
+        render(&mut app, 60, 12);
+        app.handle_key(key(KeyCode::PageUp));
+    }
+    assert!(screen_contains(&render(&mut app, 60, 12), "message 41"));
+    // A short page means the start of the room was reached
+    assert_eq!(app.handle_key(key(KeyCode::PageUp)), None);
+
+    app.handle_key(key(KeyCode::End));
+    assert!(screen_contains(&render(&mut app, 60, 12), "message 100"));
+}
+
+#[test]
+fn history_does_not_repeat_live_messages() {
+    let mut app = App::new(id(1));
+    enter_general(&mut app);
+    app.handle_event(chat(id(2), "live", 3));
+
+    app.load_history("general", vec![stored(1, "old"), stored(2, "older"), stored(3, "live")]);
+    let ids: Vec<_> = app.entries.iter().map(|entry| entry.id).collect();
+    assert_eq!(ids, vec![Some(1), Some(2), Some(3)]);
+
+    // A page for a room that was left in the meantime is dropped
+    app.load_history("rust", vec![stored(7, "elsewhere")]);
+    assert_eq!(app.entries.len(), 3);
+}
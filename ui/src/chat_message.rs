@@ -1,29 +1,247 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
 use gpui::*;
+use gpui::prelude::FluentBuilder;
+use server::{ClientId, ClientMsgId, MessageId};
+
+pub type MessageAction = Rc<dyn Fn(&mut Window, &mut App)>;
+pub type ReactionAction = Rc<dyn Fn(&str, bool, &mut Window, &mut App)>;
+
+const QUOTE_SNIPPET_LEN: usize = 60;
+const QUICK_REACTIONS: [&str; 3] = ["👍", "❤️", "😂"];
 
 #[derive(Clone, Debug)]
 pub enum MessageType {
     User,
     Other,
+    System,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeliveryState {
+    Sending,
+    Sent,
+    Failed,
+}
+
+// Callbacks wired up by ChatList, which owns the message entities
+#[derive(Clone, Default)]
+pub struct MessageActions {
+    pub edit: Option<MessageAction>,
+    pub delete: Option<MessageAction>,
+    pub reply: Option<MessageAction>,
+    pub jump_to_parent: Option<MessageAction>,
+    // Called with the emoji and whether to add (true) or remove it
+    pub toggle_reaction: Option<ReactionAction>,
 }
 
 #[derive(Clone)]
 pub struct ChatMessage {
     pub content: String,
     pub message_type: MessageType,
+    pub id: Option<MessageId>,
+    pub client_msg_id: Option<ClientMsgId>,
+    pub timestamp: Option<i64>,
+    pub delivery: DeliveryState,
+    pub seen_by: Vec<ClientId>,
+    pub edited: bool,
+    pub deleted: bool,
+    pub reply_to: Option<MessageId>,
+    pub quote: Option<String>,
+    pub reactions: BTreeMap<String, Vec<ClientId>>,
+    pub mentions: Vec<ClientId>,
+    // The local user, so their own reactions can be highlighted
+    pub viewer: Option<ClientId>,
+    pub actions: MessageActions,
+}
+
+pub fn snippet(content: &str) -> String {
+    let mut chars = content.chars();
+    let mut snippet: String = chars.by_ref().take(QUOTE_SNIPPET_LEN).collect();
+    if chars.next().is_some() {
+        snippet.push('…');
+    }
+    snippet
 }
 
 impl ChatMessage {
     pub fn new(content: impl Into<String>, message_type: MessageType) -> Self {
-        Self { content: content.into(), message_type }
+        Self {
+            content: content.into(),
+            message_type,
+            id: None,
+            client_msg_id: None,
+            timestamp: None,
+            delivery: DeliveryState::Sent,
+            seen_by: Vec::new(),
+            edited: false,
+            deleted: false,
+            reply_to: None,
+            quote: None,
+            reactions: BTreeMap::new(),
+            mentions: Vec::new(),
+            viewer: None,
+            actions: MessageActions::default(),
+        }
+    }
+
+    pub fn is_editable(&self) -> bool {
+        matches!(self.message_type, MessageType::User) && self.id.is_some() && !self.deleted
+    }
+
+    pub fn is_repliable(&self) -> bool {
+        !matches!(self.message_type, MessageType::System) && self.id.is_some() && !self.deleted
+    }
+
+    pub fn apply_edit(&mut self, new_content: String) {
+        self.content = new_content;
+        self.edited = true;
+    }
+
+    pub fn tombstone(&mut self) {
+        self.content.clear();
+        self.reactions.clear();
+        self.deleted = true;
+    }
+
+    pub fn apply_reaction(&mut self, emoji: String, client_id: ClientId, added: bool) {
+        let reactors = self.reactions.entry(emoji.clone()).or_default();
+        if added {
+            if !reactors.contains(&client_id) {
+                reactors.push(client_id);
+            }
+        } else {
+            reactors.retain(|reactor| *reactor != client_id);
+            if reactors.is_empty() {
+                self.reactions.remove(&emoji);
+            }
+        }
+    }
+
+    fn reactions_row(&self, id: MessageId, toggle: ReactionAction) -> Div {
+        let viewer = self.viewer;
+        let mut row = div()
+            .flex()
+            .flex_row()
+            .flex_wrap()
+            .gap_1()
+            .mt_1();
+
+        for (index, (emoji, reactors)) in self.reactions.iter().enumerate() {
+            let mine = viewer.is_some_and(|viewer| reactors.contains(&viewer));
+            let toggle = toggle.clone();
+            let emoji_owned = emoji.clone();
+            row = row.child(
+                div()
+                    .id(ElementId::NamedInteger(format!("reaction-{}", id).into(), index as u64))
+                    .px_2()
+                    .rounded_full()
+                    .cursor_pointer()
+                    .text_xs()
+                    .text_color(rgb(0xdcddde))
+                    .bg(if mine { rgb(0x3c4270) } else { rgb(0x2b2d31) })
+                    .border_1()
+                    .border_color(if mine { rgb(0x5865f2) } else { rgb(0x2b2d31) })
+                    .child(format!("{} {}", emoji, reactors.len()))
+                    .on_click(move |_, window, cx| toggle(&emoji_owned, !mine, window, cx))
+            );
+        }
+
+        for (index, emoji) in QUICK_REACTIONS.into_iter().enumerate() {
+            if self.reactions.contains_key(emoji) {
+                continue;
+            }
+            let toggle = toggle.clone();
+            row = row.child(
+                div()
+                    .id(ElementId::NamedInteger(format!("quick-reaction-{}", id).into(), index as u64))
+                    .px_1()
+                    .cursor_pointer()
+                    .text_xs()
+                    .opacity(0.5)
+                    .hover(|style| style.opacity(1.0))
+                    .child(emoji)
+                    .on_click(move |_, window, cx| toggle(emoji, true, window, cx))
+            );
+        }
+
+        row
+    }
+
+    pub fn with_id(mut self, id: Option<MessageId>) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn with_timestamp(mut self, timestamp: i64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn with_reply_to(mut self, reply_to: Option<MessageId>) -> Self {
+        self.reply_to = reply_to;
+        self
+    }
+
+    pub fn with_mentions(mut self, mentions: Vec<ClientId>) -> Self {
+        self.mentions = mentions;
+        self
+    }
+
+    pub fn mentions_viewer(&self) -> bool {
+        self.viewer.is_some_and(|viewer| self.mentions.contains(&viewer))
+    }
+
+    // Optimistic local copy of a message we are sending
+    pub fn sending(mut self, client_msg_id: ClientMsgId) -> Self {
+        self.client_msg_id = Some(client_msg_id);
+        self.delivery = DeliveryState::Sending;
+        self
     }
+
+    fn delivery_label(&self) -> String {
+        match (self.delivery, self.seen_by.len()) {
+            (DeliveryState::Sending, _) => "Sending…".to_string(),
+            (DeliveryState::Failed, _) => "Failed to send".to_string(),
+            (DeliveryState::Sent, 0) => "Sent".to_string(),
+            (DeliveryState::Sent, 1) => "Seen".to_string(),
+            (DeliveryState::Sent, seen) => format!("Seen by {}", seen),
+        }
+    }
+}
+
+fn action_link(id: (&'static str, MessageId), label: &'static str, action: MessageAction) -> Stateful<Div> {
+    div()
+        .id(id)
+        .cursor_pointer()
+        .text_color(rgb(0x96989d))
+        .hover(|style| style.text_color(white()))
+        .child(label)
+        .on_click(move |_, window, cx| action(window, cx))
 }
 
 impl Render for ChatMessage {
     fn render(&mut self, _window: &mut Window, _cx: &mut Context<Self>) -> impl IntoElement {
+        if matches!(self.message_type, MessageType::System) {
+            return div()
+                .flex()
+                .flex_row()
+                .justify_center()
+                .mb_2()
+                .child(
+                    div()
+                        .text_sm()
+                        .text_color(rgb(0x96989d))
+                        .child(self.content.clone())
+                );
+        }
+
         let (bg_color, text_color, alignment) = match self.message_type {
             MessageType::User => (rgb(0x5865f2), rgb(0xffffff), FlexDirection::RowReverse),
-            MessageType::Other => (rgb(0x40444b), rgb(0xdcddde), FlexDirection::Row),
+            MessageType::Other | MessageType::System => (rgb(0x40444b), rgb(0xdcddde), FlexDirection::Row),
         };
+        let is_user = matches!(self.message_type, MessageType::User);
 
         let mut container = div()
             .flex()
@@ -35,19 +253,108 @@ impl Render for ChatMessage {
             _ => container.flex_row(),
         };
 
-        if matches!(self.message_type, MessageType::User) {
+        if is_user {
             container = container.justify_end();
         }
 
-        container
-            .child(
+        let mut bubble = div()
+            .flex()
+            .flex_col();
+
+        if let Some(parent) = self.reply_to {
+            let quote = self
+                .quote
+                .clone()
+                .unwrap_or_else(|| "Original message unavailable".to_string());
+            let mut quoted = div()
+                .id(("quote", parent))
+                .mb_1()
+                .pl_2()
+                .border_l_2()
+                .border_color(rgb(0x96989d))
+                .max_w_80()
+                .text_xs()
+                .text_color(rgb(0x96989d))
+                .child(format!("↪ {}", quote));
+            if let Some(jump) = self.actions.jump_to_parent.clone() {
+                quoted = quoted
+                    .cursor_pointer()
+                    .hover(|style| style.text_color(white()))
+                    .on_click(move |_, window, cx| jump(window, cx));
+            }
+            bubble = bubble.child(quoted);
+        }
+
+        let body = if self.deleted {
+            div()
+                .p_3()
+                .rounded_lg()
+                .border_1()
+                .border_color(rgb(0x40444b))
+                .italic()
+                .text_color(rgb(0x96989d))
+                .child("Message deleted")
+        } else {
+            div()
+                .p_3()
+                .bg(bg_color)
+                .rounded_lg()
+                .text_color(text_color)
+                .max_w_80()
+                .when(self.mentions_viewer(), |body| body.border_2().border_color(rgb(0xf0b232)))
+                .child(self.content.clone())
+        };
+        bubble = bubble.child(body);
+
+        if self.edited && !self.deleted {
+            bubble = bubble.child(
+                div()
+                    .text_xs()
+                    .text_color(rgb(0x96989d))
+                    .child("(edited)")
+            );
+        }
+
+        let mut footer = div()
+            .flex()
+            .flex_row()
+            .gap_2()
+            .mt_1()
+            .text_xs();
+
+        if is_user {
+            let label_color = match self.delivery {
+                DeliveryState::Failed => rgb(0xf23f43),
+                _ => rgb(0x96989d),
+            };
+            footer = footer.child(
                 div()
-                    .p_3()
-                    .bg(bg_color)
-                    .rounded_lg()
-                    .text_color(text_color)
-                    .max_w_80()
-                    .child(self.content.clone())
-            )
+                    .text_color(label_color)
+                    .child(self.delivery_label())
+            );
+        }
+
+        if let Some(id) = self.id.filter(|_| !self.deleted) {
+            let actions = self.actions.clone();
+            if let Some(reply) = actions.reply {
+                footer = footer.child(action_link(("reply", id), "Reply", reply));
+            }
+            if let Some(edit) = actions.edit {
+                footer = footer.child(action_link(("edit", id), "Edit", edit));
+            }
+            if let Some(delete) = actions.delete {
+                footer = footer.child(action_link(("delete", id), "Delete", delete));
+            }
+        }
+
+        if let (Some(id), Some(toggle)) = (self.id.filter(|_| !self.deleted), self.actions.toggle_reaction.clone()) {
+            bubble = bubble.child(self.reactions_row(id, toggle));
+        }
+
+        if is_user {
+            bubble = bubble.items_end();
+        }
+
+        container.child(bubble.child(footer))
     }
 }
@@ -23,6 +23,10 @@ actions!(
     ]
 );
 
+pub enum InputEvent {
+    Edited,
+}
+
 pub struct TextInput {
     pub focus_handle: FocusHandle,
     pub content: SharedString,
@@ -298,6 +302,7 @@ impl EntityInputHandler for TextInput {
                 .into();
         self.selected_range = range.start + new_text.len()..range.start + new_text.len();
         self.marked_range.take();
+        cx.emit(InputEvent::Edited);
         cx.notify();
     }
 
@@ -329,6 +334,7 @@ impl EntityInputHandler for TextInput {
             .map(|new_range| new_range.start + range.start..new_range.end + range.end)
             .unwrap_or_else(|| range.start + new_text.len()..range.start + new_text.len());
 
+        cx.emit(InputEvent::Edited);
         cx.notify();
     }
 
@@ -585,6 +591,8 @@ impl Render for TextInput {
     }
 }
 
+impl EventEmitter<InputEvent> for TextInput {}
+
 impl Focusable for TextInput {
     fn focus_handle(&self, _: &App) -> FocusHandle {
         self.focus_handle.clone()
@@ -1,26 +1,151 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+
 use gpui::*;
+use server::{ClientId, MessageId};
+
+use crate::chat_message::{ChatMessage, MessageAction, MessageActions, MessageType, snippet};
 
-use crate::chat_message::ChatMessage;
+pub enum ChatListEvent {
+    EditRequested(MessageId, String),
+    DeleteRequested(MessageId),
+    ReplyRequested(MessageId, String),
+    ReactionToggled { message_id: MessageId, emoji: String, add: bool },
+    // Messages from others that were on screen in the focused window for the first time
+    Seen(Vec<MessageId>),
+}
 
 pub struct ChatList {
-    pub messages: Entity<Vec<ChatMessage>>
+    pub messages: Entity<Vec<ChatMessage>>,
+    pub scroll_handle: ScrollHandle,
+    pub rendered_count: usize,
+    pub own_id: ClientId,
+    // Off unless the user opted in to read receipts
+    pub read_receipts: bool,
+    pub receipted: HashSet<MessageId>,
+}
+
+impl EventEmitter<ChatListEvent> for ChatList {}
+
+impl ChatList {
+    pub fn scroll_to_message(&mut self, message_id: MessageId, cx: &mut Context<Self>) {
+        let index = self
+            .messages
+            .read(cx)
+            .iter()
+            .position(|message| message.id == Some(message_id));
+        if let Some(index) = index {
+            self.scroll_handle.scroll_to_item(index);
+            cx.notify();
+        }
+    }
+
+    fn emit_action(list: &WeakEntity<Self>, event: impl Fn() -> ChatListEvent + 'static) -> MessageAction {
+        let list = list.clone();
+        Rc::new(move |_: &mut Window, cx: &mut App| {
+            let _ = list.update(cx, |_, cx| cx.emit(event()));
+        })
+    }
+
+    fn actions_for(list: &WeakEntity<Self>, message: &ChatMessage) -> MessageActions {
+        let mut actions = MessageActions::default();
+        let Some(id) = message.id else {
+            return actions;
+        };
+
+        if message.is_repliable() {
+            let quote = snippet(&message.content);
+            actions.reply = Some(Self::emit_action(list, move || ChatListEvent::ReplyRequested(id, quote.clone())));
+        }
+        if message.is_editable() {
+            let content = message.content.clone();
+            actions.edit = Some(Self::emit_action(list, move || ChatListEvent::EditRequested(id, content.clone())));
+            actions.delete = Some(Self::emit_action(list, move || ChatListEvent::DeleteRequested(id)));
+        }
+        if message.is_repliable() {
+            let list = list.clone();
+            actions.toggle_reaction = Some(Rc::new(move |emoji: &str, add: bool, _: &mut Window, cx: &mut App| {
+                let emoji = emoji.to_string();
+                let _ = list.update(cx, |_, cx| {
+                    cx.emit(ChatListEvent::ReactionToggled { message_id: id, emoji, add })
+                });
+            }));
+        }
+        if let Some(parent) = message.reply_to {
+            let list = list.clone();
+            actions.jump_to_parent = Some(Rc::new(move |_: &mut Window, cx: &mut App| {
+                let _ = list.update(cx, |list, cx| list.scroll_to_message(parent, cx));
+            }));
+        }
+        actions
+    }
+
+    // Uses the item bounds of the last layout, so a message counts as seen once it has been drawn in view
+    fn report_seen(&mut self, messages: &[ChatMessage], window: &mut Window, cx: &mut Context<Self>) {
+        if !self.read_receipts || !window.is_window_active() {
+            return;
+        }
+        let viewport = self.scroll_handle.bounds();
+        let mut seen = Vec::new();
+        let mut not_laid_out = false;
+        for (index, message) in messages.iter().enumerate() {
+            let Some(id) = message.id else { continue };
+            if !matches!(message.message_type, MessageType::Other) || self.receipted.contains(&id) {
+                continue;
+            }
+            match self.scroll_handle.bounds_for_item(index) {
+                Some(bounds) if bounds.intersects(&viewport) => {
+                    self.receipted.insert(id);
+                    seen.push(id);
+                }
+                Some(_) => {}
+                None => not_laid_out = true,
+            }
+        }
+        if not_laid_out {
+            window.request_animation_frame();
+        }
+        if !seen.is_empty() {
+            cx.emit(ChatListEvent::Seen(seen));
+        }
+    }
 }
 
 impl Render for ChatList {
-    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+    fn render(&mut self, window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
         let message_entities = self.messages.read(cx).clone();
+        let list = cx.entity().downgrade();
+        self.report_seen(&message_entities, window, cx);
+
+        // Follow new messages as they arrive
+        if message_entities.len() > self.rendered_count {
+            self.scroll_handle.scroll_to_item(message_entities.len() - 1);
+        }
+        self.rendered_count = message_entities.len();
 
         div()
+            .id("chat-list")
             .flex()
             .flex_col()
             .h_full()
             .w_full()
             .p_3()
+            .overflow_y_scroll()
+            .track_scroll(&self.scroll_handle)
             .children(
                 message_entities
                     .iter()
                     .map(|message| {
-                        cx.new(|_| message.clone())
+                        let mut message = message.clone();
+                        message.quote = message.reply_to.and_then(|parent| {
+                            message_entities
+                                .iter()
+                                .find(|candidate| candidate.id == Some(parent))
+                                .map(|parent| if parent.deleted { "Message deleted".to_string() } else { snippet(&parent.content) })
+                        });
+                        message.viewer = Some(self.own_id);
+                        message.actions = Self::actions_for(&list, &message);
+                        cx.new(|_| message)
                     })
                     .collect::<Vec<_>>()
             )
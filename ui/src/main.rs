@@ -2,43 +2,276 @@ mod input;
 mod chat_message;
 mod view;
 mod list;
+mod members;
 
 use anyhow::Result;
-use client_lib::Client;
+use client_lib::{ClientEvent, ClientHandle, EventStream, ReconnectPolicy, TypingIndicators, short_id};
 use gpui::*;
-use server::Message;
+use server::{Message, PresenceEvent, UserInfo, UserStatus};
+use std::time::Duration;
 use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio_stream::StreamExt;
 
 use crate::{
-    chat_message::{ChatMessage, MessageType},
-    view::MainView,
+    chat_message::{ChatMessage, DeliveryState, MessageType},
+    members::member_name,
+    view::{MainView, RoomDetails},
 };
 
+const AUTO_AWAY_AFTER: Duration = Duration::from_secs(5 * 60);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let client = Client::connect("127.0.0.1:8080")?;
+    let mut builder = ClientHandle::builder("127.0.0.1:8080")
+        .echo(true)
+        .heartbeat_interval(HEARTBEAT_INTERVAL)
+        .reconnect(ReconnectPolicy::backoff(Duration::from_secs(1), Duration::from_secs(30)));
+    if let Ok(nickname) = std::env::var("CHAT_NICKNAME") {
+        builder = builder.nickname(nickname);
+    }
+    if let Some(client_id) = std::env::var("CHAT_CLIENT_ID").ok().and_then(|id| id.parse().ok()) {
+        builder = builder.client_id(client_id);
+    }
+    if let Ok(token) = std::env::var("CHAT_TOKEN") {
+        builder = builder.token(token);
+    }
+    // Read receipts are only sent when asked for, and then only for messages shown in the focused window
+    let read_receipts = std::env::var("CHAT_READ_RECEIPTS").is_ok_and(|value| value == "1");
+    // The window opens right away, connection problems are reported in the message list
+    let (client, events) = builder.connect_in_background()?;
     let client_clone = client.clone();
+    let own_id = client.client_id();
 
-    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
-    let _ = spawn_message_handler(client, tx);
+    let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
+    let _ = spawn_message_handler(client, events, tx);
 
     Application::new().run(|cx: &mut App| {
         let messages_entity = cx.new(|_cx| Vec::new());
+        let members_entity = cx.new(|_cx| Vec::<UserInfo>::new());
+        let typing_entity = cx.new(|_cx| TypingIndicators::new());
+        let room_entity = cx.new(|_cx| RoomDetails::default());
 
         let messages_entity_clone = messages_entity.clone();
+        let members_entity_clone = members_entity.clone();
+        let typing_entity_clone = typing_entity.clone();
+        let room_entity_clone = room_entity.clone();
+        cx.spawn(async move |cx| {
+            while let Some(message) = rx.recv().await {
+                match message {
+                    Message::Chat { content, client_id, timestamp, id, client_msg_id, reply_to, mentions, room: _ } => {
+                        let _ = typing_entity_clone.update(cx, |typing, _| typing.clear(&client_id));
+                        let _ = messages_entity_clone.update(cx, |entity, _| {
+                            if client_id != own_id {
+                                entity.push(
+                                    ChatMessage::new(content, MessageType::Other)
+                                        .with_id(id)
+                                        .with_timestamp(timestamp)
+                                        .with_reply_to(reply_to)
+                                        .with_mentions(mentions)
+                                );
+                                return;
+                            }
+
+                            // Our own message echoed back: replace the optimistic copy and move it
+                            // to the position the server broadcast it in
+                            let optimistic = client_msg_id
+                                .and_then(|sent| entity.iter().position(|message| message.client_msg_id == Some(sent)))
+                                .map(|index| entity.remove(index));
+                            let mut echoed = optimistic.unwrap_or_else(|| ChatMessage::new("", MessageType::User));
+                            echoed.content = content;
+                            echoed.reply_to = reply_to;
+                            echoed.mentions = mentions;
+                            echoed.id = id;
+                            echoed.timestamp = Some(timestamp);
+                            echoed.delivery = DeliveryState::Sent;
+                            entity.push(echoed);
+                        });
+                    }
+                    Message::Edit { message_id, new_content, .. } => {
+                        let _ = messages_entity_clone.update(cx, |entity, _| {
+                            if let Some(edited) = entity.iter_mut().find(|message| message.id == Some(message_id)) {
+                                edited.apply_edit(new_content);
+                            }
+                        });
+                    }
+                    Message::Delete { message_id, .. } => {
+                        let _ = messages_entity_clone.update(cx, |entity, _| {
+                            if let Some(deleted) = entity.iter_mut().find(|message| message.id == Some(message_id)) {
+                                deleted.tombstone();
+                            }
+                        });
+                    }
+                    Message::Reaction { message_id, emoji, client_id, added, .. } => {
+                        let _ = messages_entity_clone.update(cx, |entity, _| {
+                            if let Some(reacted) = entity.iter_mut().find(|message| message.id == Some(message_id)) {
+                                reacted.apply_reaction(emoji, client_id, added);
+                            }
+                        });
+                    }
+                    Message::System { content } => {
+                        let _ = messages_entity_clone.update(cx, |entity, _| {
+                            entity.push(ChatMessage::new(content, MessageType::System));
+                        });
+                    }
+                    // The optimistic copy is marked as failed once `accepted` returns the rejection
+                    Message::Nack { reason, .. } => {
+                        let _ = messages_entity_clone.update(cx, |entity, _| {
+                            entity.push(ChatMessage::new(format!("Not sent: {}", reason), MessageType::System));
+                        });
+                    }
+                    Message::Credentials { token } => {
+                        let notice = format!("To connect as yourself again, set CHAT_CLIENT_ID={} CHAT_TOKEN={}", own_id, token);
+                        let _ = messages_entity_clone.update(cx, |entity, _| {
+                            entity.push(ChatMessage::new(notice, MessageType::System));
+                        });
+                    }
+                    Message::ReadReceipt { message_id, client_id, .. } => {
+                        let _ = messages_entity_clone.update(cx, |entity, _| {
+                            if let Some(read) = entity.iter_mut().find(|message| message.id == Some(message_id))
+                                && !read.seen_by.contains(&client_id)
+                            {
+                                read.seen_by.push(client_id);
+                            }
+                        });
+                    }
+                    Message::Presence { client_id, nickname, event, timestamp: _ } => {
+                        let notice = match event {
+                            PresenceEvent::Joined => format!("{} joined", nickname),
+                            PresenceEvent::Left => format!("{} left", nickname),
+                        };
+                        let _ = members_entity_clone.update(cx, |members, _| match event {
+                            PresenceEvent::Joined => {
+                                if !members.iter().any(|member| member.client_id == client_id) {
+                                    members.push(UserInfo {
+                                        client_id,
+                                        nickname,
+                                        status: UserStatus::Online,
+                                        status_text: None,
+                                    });
+                                }
+                            }
+                            PresenceEvent::Left => members.retain(|member| member.client_id != client_id),
+                        });
+                        let _ = messages_entity_clone.update(cx, |entity, _| {
+                            entity.push(ChatMessage::new(notice, MessageType::System));
+                        });
+                    }
+                    Message::WhoList { clients } => {
+                        let _ = members_entity_clone.update(cx, |members, _| {
+                            *members = clients;
+                        });
+                    }
+                    Message::StatusChanged { client_id, status, status_text } => {
+                        let _ = members_entity_clone.update(cx, |members, _| {
+                            match members.iter_mut().find(|member| member.client_id == client_id) {
+                                Some(member) => {
+                                    member.status = status;
+                                    member.status_text = status_text;
+                                }
+                                None => members.push(UserInfo {
+                                    client_id,
+                                    nickname: short_id(&client_id),
+                                    status,
+                                    status_text,
+                                }),
+                            }
+                        });
+                    }
+                    Message::Mention { message_id, from, room: _ } => {
+                        // Mentions of messages already on screen are highlighted in place
+                        let on_screen = messages_entity_clone
+                            .read_with(cx, |entity, _| entity.iter().any(|message| message.id == Some(message_id)))
+                            .unwrap_or(false);
+                        if !on_screen {
+                            let author = members_entity_clone
+                                .read_with(cx, |members, _| member_name(members, &from))
+                                .unwrap_or_else(|_| short_id(&from));
+                            let _ = messages_entity_clone.update(cx, |entity, _| {
+                                entity.push(ChatMessage::new(format!("{} mentioned you", author), MessageType::System));
+                            });
+                        }
+                    }
+                    Message::NicknameChanged { client_id, nickname } => {
+                        let previous = members_entity_clone
+                            .update(cx, |members, _| {
+                                let member = members.iter_mut().find(|member| member.client_id == client_id)?;
+                                Some(std::mem::replace(&mut member.nickname, nickname.clone()))
+                            })
+                            .ok()
+                            .flatten()
+                            .unwrap_or_else(|| short_id(&client_id));
+                        let _ = messages_entity_clone.update(cx, |entity, _| {
+                            let notice = format!("{} is now known as {}", previous, nickname);
+                            entity.push(ChatMessage::new(notice, MessageType::System));
+                        });
+                    }
+                    // Only the new room's messages arrive from now on, so start from an empty list
+                    Message::RoomChanged { room, topic, description } => {
+                        let moved = room_entity_clone
+                            .update(cx, |details, _| {
+                                let moved = details.room != room;
+                                *details = RoomDetails { room: room.clone(), topic: topic.clone(), description };
+                                moved
+                            })
+                            .unwrap_or(false);
+                        if moved {
+                            let _ = typing_entity_clone.update(cx, |typing, _| *typing = TypingIndicators::new());
+                            let _ = messages_entity_clone.update(cx, |entity, _| {
+                                entity.clear();
+                                entity.push(ChatMessage::new(format!("You joined #{}", room), MessageType::System));
+                                if let Some(topic) = topic {
+                                    entity.push(ChatMessage::new(format!("Topic: {}", topic), MessageType::System));
+                                }
+                            });
+                        }
+                    }
+                    Message::RoleChanged { client_id, role, room } => {
+                        let name = members_entity_clone
+                            .read_with(cx, |members, _| member_name(members, &client_id))
+                            .unwrap_or_else(|_| short_id(&client_id));
+                        let scope = room.map(|room| format!(" in #{}", room)).unwrap_or_default();
+                        let notice = match role {
+                            Some(role) => format!("{} is now a {}{}", name, role, scope),
+                            None => format!("{} no longer has a role{}", name, scope),
+                        };
+                        let _ = messages_entity_clone.update(cx, |entity, _| {
+                            entity.push(ChatMessage::new(notice, MessageType::System));
+                        });
+                    }
+                    Message::RoomUpdated { room, topic, description } => {
+                        let _ = room_entity_clone.update(cx, |details, _| {
+                            if details.room == room {
+                                details.topic = topic;
+                                details.description = description;
+                            }
+                        });
+                    }
+                    Message::Typing { client_id, room: _ } => {
+                        let _ = typing_entity_clone.update(cx, |typing, _| typing.record(client_id));
+                    }
+                    _ => continue,
+                }
+                let _ = cx.refresh();
+            }
+        }).detach();
+
+        // Expire stale typing indicators
+        let typing_entity_clone = typing_entity.clone();
         cx.spawn(async move |cx| {
             loop {
-                if let Some(content) = rx.recv().await {
-                    let _ = messages_entity_clone.update(cx, |entity, _| {
-                        entity.push(ChatMessage::new(content, MessageType::Other));
-                    });
+                cx.background_executor().timer(Duration::from_secs(1)).await;
+                if let Ok(true) = typing_entity_clone.update(cx, |typing, _| typing.prune()) {
                     let _ = cx.refresh();
                 }
             }
         }).detach();
 
-        let _ = cx.open_window(WindowOptions::default(), |_, app| {
-            app.new(|app| MainView::new(app, messages_entity, client_clone))
+        let _ = cx.open_window(WindowOptions::default(), |window, app| {
+            app.new(|app| {
+                MainView::new(window, app, messages_entity, members_entity, typing_entity, room_entity, client_clone)
+                    .with_read_receipts(read_receipts, app)
+            })
         });
     });
 
@@ -46,20 +279,38 @@ async fn main() -> Result<()> {
 }
 
 fn spawn_message_handler(
-    client: Client,
-    app_tx: UnboundedSender<String>,
+    client: ClientHandle,
+    mut events: EventStream,
+    app_tx: UnboundedSender<Message>,
 ) -> tokio::task::JoinHandle<()> {
     tokio::spawn(async move {
-        loop {
-            if let Some(message) = client.message_receiver.lock().await.recv().await {
-                match message {
-                    Message::Chat { content, client_id: _, timestamp: _ } => {
-                        let _ = app_tx.send(content);
-                        continue;
-                    }
-                    _ => continue
+        let _ = client.request_who().await;
+        let auto_away = client.enable_auto_away(AUTO_AWAY_AFTER);
+
+        while let Some(event) = events.next().await {
+            match event {
+                ClientEvent::Message(message) => {
+                    let _ = app_tx.send(message);
+                }
+                ClientEvent::Lagged { skipped } => {
+                    let _ = app_tx.send(Message::system(&format!("Missed {} messages while busy", skipped)));
+                }
+                ClientEvent::ConnectFailed { reason } => {
+                    let _ = app_tx.send(Message::system(&format!("Could not connect: {}", reason)));
+                }
+                ClientEvent::Reconnecting { attempt, delay } => {
+                    let notice = format!("Connection lost, reconnecting in {}s (attempt {})", delay.as_secs(), attempt);
+                    let _ = app_tx.send(Message::system(&notice));
+                }
+                ClientEvent::Reconnected => {
+                    let _ = app_tx.send(Message::system("Reconnected"));
+                    let _ = client.request_who().await;
+                }
+                ClientEvent::Disconnected => {
+                    let _ = app_tx.send(Message::system("Disconnected from the server"));
                 }
             }
         }
+        auto_away.abort();
     })
 }
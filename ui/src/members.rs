@@ -0,0 +1,137 @@
+use client_lib::{ClientHandle, display_name};
+use gpui::*;
+use gpui::prelude::FluentBuilder;
+use server::{ClientId, UserInfo, UserStatus};
+
+pub struct MemberList {
+    pub members: Entity<Vec<UserInfo>>,
+    pub client: ClientHandle,
+}
+
+// Nickname of a known member, falling back to the short id
+pub fn member_name(members: &[UserInfo], client_id: &ClientId) -> String {
+    let member = members.iter().find(|member| member.client_id == *client_id);
+    display_name(member.map(|member| member.nickname.as_str()), client_id)
+}
+
+fn status_color(status: UserStatus) -> Rgba {
+    match status {
+        UserStatus::Online => rgb(0x23a55a),
+        UserStatus::Away => rgb(0xf0b232),
+        UserStatus::Busy => rgb(0xf23f43),
+        UserStatus::Invisible => rgb(0x80848e),
+    }
+}
+
+fn status_label(status: UserStatus) -> &'static str {
+    match status {
+        UserStatus::Online => "Online",
+        UserStatus::Away => "Away",
+        UserStatus::Busy => "Busy",
+        UserStatus::Invisible => "Invisible",
+    }
+}
+
+impl MemberList {
+    fn set_status(&mut self, status: UserStatus, cx: &mut Context<Self>) {
+        let own_id = self.client.client_id();
+        self.members.update(cx, |members, _| {
+            if let Some(own) = members.iter_mut().find(|member| member.client_id == own_id) {
+                own.status = status;
+            }
+        });
+
+        let client = self.client.clone();
+        cx.spawn(async move |_, _| {
+            let _ = client.set_status(status, None).await;
+        }).detach();
+    }
+}
+
+impl Render for MemberList {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let members = self.members.read(cx).clone();
+        let own_id = self.client.client_id();
+
+        div()
+            .flex()
+            .flex_col()
+            .h_full()
+            .w_48()
+            .p_3()
+            .bg(rgb(0x2b2d31))
+            .border_l_1()
+            .border_color(rgb(0x202225))
+            .child(
+                div()
+                    .mb_2()
+                    .text_xs()
+                    .font_weight(FontWeight::SEMIBOLD)
+                    .text_color(rgb(0x96989d))
+                    .child(format!("ONLINE — {}", members.len()))
+            )
+            .child(
+                div()
+                    .flex()
+                    .flex_col()
+                    .flex_1()
+                    .children(
+                        members
+                            .iter()
+                            .map(|member| {
+                                let label = if member.client_id == own_id {
+                                    format!("{} (you)", member.nickname)
+                                } else {
+                                    member.nickname.clone()
+                                };
+
+                                div()
+                                    .flex()
+                                    .flex_col()
+                                    .py_1()
+                                    .child(
+                                        div()
+                                            .flex()
+                                            .flex_row()
+                                            .items_center()
+                                            .gap_2()
+                                            .child(div().size_2().rounded_full().bg(status_color(member.status)))
+                                            .child(div().text_sm().text_color(rgb(0xdcddde)).child(label))
+                                    )
+                                    .when_some(member.status_text.clone(), |this, text| {
+                                        this.child(div().pl_4().text_xs().text_color(rgb(0x96989d)).child(text))
+                                    })
+                            })
+                            .collect::<Vec<_>>()
+                    )
+            )
+            .child(
+                // Own status picker
+                div()
+                    .flex()
+                    .flex_row()
+                    .flex_wrap()
+                    .gap_1()
+                    .pt_2()
+                    .border_t_1()
+                    .border_color(rgb(0x202225))
+                    .children(
+                        [UserStatus::Online, UserStatus::Away, UserStatus::Busy, UserStatus::Invisible]
+                            .into_iter()
+                            .map(|status| {
+                                div()
+                                    .id(status_label(status))
+                                    .px_1()
+                                    .rounded_sm()
+                                    .cursor_pointer()
+                                    .text_xs()
+                                    .text_color(status_color(status))
+                                    .hover(|style| style.bg(rgb(0x36393f)))
+                                    .child(status_label(status))
+                                    .on_click(cx.listener(move |this, _, _, cx| this.set_status(status, cx)))
+                            })
+                            .collect::<Vec<_>>()
+                    )
+            )
+    }
+}
@@ -1,19 +1,68 @@
-use client_lib::Client;
+use client_lib::{ClientHandle, TypingIndicators};
 use gpui::*;
+use gpui::prelude::FluentBuilder;
+use std::collections::HashSet;
+use server::{ClientId, MessageId, RoomId, UserInfo, DEFAULT_ROOM};
+use server::commands::is_command;
 
 use crate::{input::*};
-use crate::chat_message::{ChatMessage, MessageType};
-use crate::list::ChatList;
+use crate::chat_message::{ChatMessage, DeliveryState, MessageType};
+use crate::list::{ChatList, ChatListEvent};
+use crate::members::{MemberList, member_name};
+
+// The room this client is in, as last announced by the server
+#[derive(Clone)]
+pub struct RoomDetails {
+    pub room: RoomId,
+    pub topic: Option<String>,
+    pub description: Option<String>,
+}
+
+impl Default for RoomDetails {
+    fn default() -> Self {
+        Self {
+            room: DEFAULT_ROOM.to_string(),
+            topic: None,
+            description: None,
+        }
+    }
+}
 
 pub struct MainView {
     input: Entity<TextInput>,
     messages: Entity<Vec<ChatMessage>>,
     list: Entity<ChatList>,
-    client: Client,
+    members: Entity<MemberList>,
+    typing: Entity<TypingIndicators>,
+    room: Entity<RoomDetails>,
+    editing: Option<MessageId>,
+    replying_to: Option<(MessageId, String)>,
+    client: ClientHandle,
+}
+
+fn typing_label(typers: &[ClientId], members: &[UserInfo]) -> Option<String> {
+    match typers {
+        [] => None,
+        [one] => Some(format!("{} is typing…", member_name(members, one))),
+        [one, two] => Some(format!(
+            "{} and {} are typing…",
+            member_name(members, one),
+            member_name(members, two)
+        )),
+        _ => Some("Several people are typing…".to_string()),
+    }
 }
 
 impl MainView {
-    pub fn new(app: &mut App, messages: Entity<Vec<ChatMessage>>, client: Client) -> Self {
+    pub fn new(
+        window: &mut Window,
+        app: &mut Context<Self>,
+        messages: Entity<Vec<ChatMessage>>,
+        members: Entity<Vec<UserInfo>>,
+        typing: Entity<TypingIndicators>,
+        room: Entity<RoomDetails>,
+        client: ClientHandle,
+    ) -> Self {
         app.bind_keys([
             KeyBinding::new("backspace", Backspace, None),
             KeyBinding::new("delete", Delete, None),
@@ -41,27 +90,151 @@ impl MainView {
             last_bounds: None,
             is_selecting: false,
         });
+        app.subscribe(&input, Self::handle_input_event).detach();
 
         let messages_clone = messages.clone();
         let list = app.new(|_| ChatList {
             messages: messages_clone,
+            scroll_handle: ScrollHandle::new(),
+            rendered_count: 0,
+            own_id: client.client_id(),
+            read_receipts: false,
+            receipted: HashSet::new(),
+        });
+        app.subscribe(&list, Self::handle_list_event).detach();
+        // Messages that arrived while the window was in the background are seen once it is focused
+        let list_clone = list.clone();
+        app.observe_window_activation(window, move |_, _, cx| list_clone.update(cx, |_, cx| cx.notify()))
+            .detach();
+
+        let members = app.new(|_| MemberList {
+            members,
+            client: client.clone(),
         });
 
         Self {
             input,
             messages,
             list,
+            members,
+            typing,
+            room,
+            editing: None,
+            replying_to: None,
             client
         }
     }
 
+    // Send read receipts for messages shown in the focused window
+    pub fn with_read_receipts(self, enabled: bool, cx: &mut Context<Self>) -> Self {
+        self.list.update(cx, |list, _| list.read_receipts = enabled);
+        self
+    }
+
+    fn handle_list_event(&mut self, _list: Entity<ChatList>, event: &ChatListEvent, cx: &mut Context<Self>) {
+        match event {
+            ChatListEvent::EditRequested(message_id, content) => {
+                self.editing = Some(*message_id);
+                let content: SharedString = content.clone().into();
+                self.input.update(cx, |input, _cx| {
+                    input.selected_range = content.len()..content.len();
+                    input.content = content;
+                });
+                cx.notify();
+            }
+            ChatListEvent::ReplyRequested(message_id, quote) => {
+                self.replying_to = Some((*message_id, quote.clone()));
+                cx.notify();
+            }
+            ChatListEvent::ReactionToggled { message_id, emoji, add } => {
+                let client = self.client.clone();
+                let (message_id, emoji, add) = (*message_id, emoji.clone(), *add);
+                cx.spawn(async move |_, _| {
+                    let _ = if add {
+                        client.react(message_id, &emoji).await
+                    } else {
+                        client.unreact(message_id, &emoji).await
+                    };
+                }).detach();
+            }
+            ChatListEvent::Seen(message_ids) => {
+                let client = self.client.clone();
+                let message_ids = message_ids.clone();
+                cx.spawn(async move |_, _| {
+                    for message_id in message_ids {
+                        let _ = client.mark_read(message_id).await;
+                    }
+                }).detach();
+            }
+            ChatListEvent::DeleteRequested(message_id) => {
+                let client = self.client.clone();
+                let message_id = *message_id;
+                cx.spawn(async move |_, _| {
+                    let _ = client.delete_message(message_id).await;
+                }).detach();
+            }
+        }
+    }
+
+    fn handle_input_event(&mut self, input: Entity<TextInput>, event: &InputEvent, cx: &mut Context<Self>) {
+        match event {
+            InputEvent::Edited => {
+                if input.read(cx).content.is_empty() {
+                    return;
+                }
+                let client = self.client.clone();
+                cx.spawn(async move |_, _| {
+                    let _ = client.notify_typing().await;
+                }).detach();
+            }
+        }
+    }
+
+    fn cancel_reply(&mut self, _: &ClickEvent, _window: &mut Window, cx: &mut Context<Self>) {
+        self.replying_to = None;
+        cx.notify();
+    }
+
     pub fn handle_send_button(&mut self, _: &MouseUpEvent, _window: &mut Window, cx: &mut Context<Self>) {
         let message = self.input.read(cx).content.clone();
+        if let Some(message_id) = self.editing.take() {
+            self.input.update(cx, |input, _cx| {
+                input.content = "".into();
+                input.selected_range = 0..0;
+            });
+            cx.notify();
+
+            // The server broadcasts the edit back to us, which updates the bubble
+            let client = self.client.clone();
+            let new_content = message.to_string();
+            cx.spawn(async move |_, _| {
+                let _ = client.edit_message(message_id, &new_content).await;
+            }).detach();
+            return;
+        }
+
+        if is_command(message.trim()) {
+            self.input.update(cx, |input, _cx| {
+                input.content = "".into();
+                input.selected_range = 0..0;
+            });
+            cx.notify();
+
+            let client = self.client.clone();
+            let line = message.trim().to_string();
+            cx.spawn(async move |_, _| {
+                let _ = client.send_command(&line).await;
+            }).detach();
+            return;
+        }
+
         if !message.trim().is_empty() {
             let message_str = message.as_ref();
 
+            let client_msg_id = self.client.next_client_msg_id();
+            let reply_to = self.replying_to.take().map(|(parent, _)| parent);
             self.messages.update(cx, |e, _cx| {
-                e.push(ChatMessage::new(message_str, MessageType::User));
+                e.push(ChatMessage::new(message_str, MessageType::User).with_reply_to(reply_to).sending(client_msg_id));
             });
 
             self.input.update(cx, |input, _cx| {
@@ -72,9 +245,27 @@ impl MainView {
             cx.notify();
 
             let client = self.client.clone();
+            let messages = self.messages.clone();
             let message_to_send = message_str.to_string();
-            cx.spawn(async move |_, _| {
-                let _ = client.send_message(&message_to_send).await;
+            cx.spawn(async move |_, cx| {
+                let delivery = match client.send_chat(&message_to_send, client_msg_id, reply_to).await {
+                    Ok(pending) => pending.accepted().await,
+                    Err(e) => Err(e),
+                };
+                // The echo may already have moved the bubble, so look it up by its client_msg_id
+                let _ = messages.update(cx, |e, _cx| {
+                    if let Some(sent) = e.iter_mut().find(|message| message.client_msg_id == Some(client_msg_id)) {
+                        match delivery {
+                            Ok(delivery) => {
+                                sent.id = Some(delivery.server_msg_id);
+                                sent.timestamp = Some(delivery.timestamp);
+                                sent.delivery = DeliveryState::Sent;
+                            }
+                            Err(_) => sent.delivery = DeliveryState::Failed,
+                        }
+                    }
+                });
+                let _ = cx.refresh();
             }).detach();
         }
     }
@@ -82,6 +273,7 @@ impl MainView {
 
 impl Render for MainView {
     fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let room = self.room.read(cx).clone();
         div()
             .flex()
             .flex_col()
@@ -92,7 +284,7 @@ impl Render for MainView {
                 // Chat header
                 div()
                     .flex()
-                    .items_center()
+                    .flex_col()
                     .px_4()
                     .py_3()
                     .bg(rgb(0x36393f))
@@ -100,16 +292,85 @@ impl Render for MainView {
                     .border_color(rgb(0x202225))
                     .child(
                         div()
-                            .text_lg()
-                            .font_weight(FontWeight::SEMIBOLD)
-                            .text_color(white())
-                            .child("Chat")
+                            .flex()
+                            .flex_row()
+                            .items_center()
+                            .gap_3()
+                            .child(
+                                div()
+                                    .text_lg()
+                                    .font_weight(FontWeight::SEMIBOLD)
+                                    .text_color(white())
+                                    .child(format!("#{}", room.room))
+                            )
+                            .when_some(room.topic.clone(), |this, topic| {
+                                this.child(
+                                    div()
+                                        .text_sm()
+                                        .text_color(rgb(0xb9bbbe))
+                                        .child(topic)
+                                )
+                            })
+                    )
+                    .when_some(room.description.clone(), |this, description| {
+                        this.child(
+                            div()
+                                .text_xs()
+                                .text_color(rgb(0x96989d))
+                                .child(description)
+                        )
+                    })
+            )
+            .child(
+                // Messages area and member sidebar
+                div()
+                    .flex()
+                    .flex_row()
+                    .flex_1()
+                    .overflow_hidden()
+                    .child(
+                        div()
+                            .flex_1()
+                            .child(self.list.clone())
                     )
+                    .child(self.members.clone())
             )
             .child(
-                // Messages area
-                self.list.clone()
+                // Typing indicator
+                div()
+                    .h_5()
+                    .px_4()
+                    .text_xs()
+                    .text_color(rgb(0x96989d))
+                    .children(typing_label(
+                        &self.typing.read(cx).active(),
+                        self.members.read(cx).members.read(cx),
+                    ))
             )
+            .when_some(self.replying_to.clone(), |this, (_, quote)| {
+                this.child(
+                    // Reply target
+                    div()
+                        .flex()
+                        .flex_row()
+                        .items_center()
+                        .gap_2()
+                        .px_4()
+                        .py_1()
+                        .bg(rgb(0x2b2d31))
+                        .text_xs()
+                        .text_color(rgb(0x96989d))
+                        .child(div().flex_1().child(format!("Replying to: {}", quote)))
+                        .child(
+                            div()
+                                .id("cancel-reply")
+                                .cursor_pointer()
+                                .hover(|style| style.text_color(white()))
+                                .child("Cancel")
+                                .on_click(cx.listener(Self::cancel_reply))
+                        )
+                )
+            })
             .child(
                 // Input area
                 div()
@@ -135,7 +396,7 @@ impl Render for MainView {
                             .cursor_pointer()
                             .text_color(white())
                             .font_weight(FontWeight::MEDIUM)
-                            .child("Send")
+                            .child(if self.editing.is_some() { "Save" } else { "Send" })
                             .on_mouse_up(MouseButton::Left, cx.listener(Self::handle_send_button))
                     )
             )